@@ -0,0 +1,32 @@
+use automaton::{bytes, Regex};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const ASCII: &str = "The quick brown fox jumps over the lazy dog, and mail to fox@example.com \
+                     bounces after 42 tries.\n";
+const UNICODE: &str = "Le renard brun rapide saute par-dessus le chien paresseux, à 42 mètres \
+                       de l'été, écrit à renard@exemple.fr.\n";
+
+fn search(c: &mut Criterion) {
+    for (name, line) in [("ascii", ASCII), ("unicode", UNICODE)] {
+        let text = line.repeat(1_000);
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+
+        for pattern in [r"\w+@\w+\.\w+", r"[0-9]+ \w+", "zzz"] {
+            let regex = Regex::new(pattern).unwrap();
+            let bytes = bytes::Regex::new(pattern).unwrap();
+
+            group.bench_function(format!("find_iter {pattern}"), |b| {
+                b.iter(|| regex.find_iter(&text).count())
+            });
+            group.bench_function(format!("bytes find_iter {pattern}"), |b| {
+                b.iter(|| bytes.find_iter(text.as_bytes()).count())
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
@@ -0,0 +1,204 @@
+//! Regexes that match `&[u8]` rather than `&str`, for text that may not be valid UTF-8.
+//!
+//! A pattern is compiled as for `&str`, and then each char it matches is replaced by the
+//! byte sequences of its UTF-8 encoding. By default, a char class therefore only matches
+//! valid UTF-8; see `RegexBuilder::allow_invalid_utf8` for matching any byte. The word
+//! boundary assertions see one byte at a time, so only the ASCII word chars count as word
+//! chars for them.
+
+use crate::{
+    deterministic_finite_automaton::{Automaton as DFA, Look},
+    find_from, Budget, Match, RegexBuilder,
+};
+use anyhow::Result;
+
+impl RegexBuilder {
+    pub fn build_bytes(&self) -> Result<Regex> {
+        let mut nfa = self.build_nfa()?.into_bytes(self.allow_invalid_utf8);
+        let mut budget = Budget::new(self.dfa_work_limit);
+        let (forward, reverse) = self.build_search_dfas(&nfa, &mut budget)?;
+
+        let mut dfa = self.build_dfa_without_epsilons(&mut nfa, &mut budget)?;
+        dfa.minimize();
+
+        Ok(Regex {
+            automaton: dfa,
+            forward,
+            reverse,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    automaton: DFA,
+    forward: DFA,
+    reverse: DFA,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        RegexBuilder::new(pattern).build_bytes()
+    }
+
+    pub fn matches(&self, text: &[u8]) -> bool {
+        let mut current = self.automaton.start(Look::Boundary);
+
+        for b in text {
+            match self.automaton.next(current, char::from(*b)) {
+                Some(destination) => current = destination,
+                None => return false,
+            }
+        }
+
+        self.automaton.is_accepting(current, Look::Boundary)
+    }
+
+    /// Returns true if the regex matches anywhere in the text.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the leftmost-first match in the text.
+    pub fn find(&self, text: &[u8]) -> Option<Match> {
+        self.find_at(text, 0)
+    }
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t [u8]) -> FindIter<'r, 't> {
+        FindIter {
+            regex: self,
+            text,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    fn find_at(&self, text: &[u8], start: usize) -> Option<Match> {
+        find_from(
+            || self.forward.longest_prefix_bytes(text, start),
+            |end| self.reverse.longest_suffix_bytes(text, start, end),
+        )
+    }
+}
+
+/// An iterator over all non-overlapping matches in a text.
+///
+/// An empty match immediately following another match is skipped, and the
+/// search always advances by at least one byte after an empty match.
+#[derive(Debug, Clone)]
+pub struct FindIter<'r, 't> {
+    regex: &'r Regex,
+    text: &'t [u8],
+    last_end: usize,
+    last_match: Option<usize>,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.last_end > self.text.len() {
+                return None;
+            }
+
+            let m = self.regex.find_at(self.text, self.last_end)?;
+
+            if m.is_empty() {
+                self.last_end = m.end() + 1;
+
+                if Some(m.end()) == self.last_match {
+                    continue;
+                }
+            } else {
+                self.last_end = m.end();
+            }
+
+            self.last_match = Some(m.end());
+            return Some(m);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bytes::Regex, RegexBuilder};
+
+    fn ranges(regex: &Regex, text: &[u8]) -> Vec<std::ops::Range<usize>> {
+        regex.find_iter(text).map(|m| m.range()).collect()
+    }
+
+    #[test]
+    fn test_matches() {
+        let regex = Regex::new("(a|b)*a(a|b)(a|b)").unwrap();
+
+        assert!(regex.matches(b"ababa"));
+        assert!(!regex.matches(b"babab"));
+
+        let regex = Regex::new("a.c").unwrap();
+
+        assert!(regex.matches("aéc".as_bytes()));
+        assert!(regex.matches("a\u{10FFFF}c".as_bytes()));
+        assert!(!regex.matches(b"a\xFFc"));
+        assert!(!regex.matches(b"a\xC3c"));
+        assert!(!regex.matches(b"a\xED\xA0\x80c"));
+
+        let regex = Regex::new("[^a-c]+").unwrap();
+
+        assert!(regex.matches("xαβ漢".as_bytes()));
+        assert!(!regex.matches(b"x\x80"));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let regex = Regex::new(r"\p{Greek}+").unwrap();
+
+        assert_eq!(ranges(&regex, b"\xFFab \xCE\xB1\xCE\xB2\xFF"), vec![4..8]);
+
+        let regex = Regex::new("é|ü").unwrap();
+
+        assert_eq!(ranges(&regex, b"\xC3\xA9\xC3\xC3\xBC"), vec![0..2, 3..5]);
+
+        let regex = Regex::new("").unwrap();
+
+        assert_eq!(ranges(&regex, "é".as_bytes()), vec![0..0, 1..1, 2..2]);
+
+        let regex = Regex::new("a*").unwrap();
+
+        assert_eq!(ranges(&regex, b"\xFFaa\xFF"), vec![0..0, 1..3, 4..4]);
+    }
+
+    #[test]
+    fn test_allow_invalid_utf8() {
+        let regex = |pattern| {
+            RegexBuilder::new(pattern)
+                .allow_invalid_utf8(true)
+                .build_bytes()
+                .unwrap()
+        };
+
+        assert!(regex("a.c").matches(b"a\xFFc"));
+        assert!(regex("a.c").matches("aéc".as_bytes()));
+        assert!(!regex("a.c").matches(b"a\nc"));
+        assert!(regex("a[^b]c").matches(b"a\xC3c"));
+        assert!(!regex(r"a\pLc").matches(b"a\xC3c"));
+        assert_eq!(
+            ranges(&regex("."), b"\xC3\xA9\xC3a"),
+            vec![0..2, 2..3, 3..4]
+        );
+        assert_eq!(ranges(&regex(".+?"), b"\xF0\x9F\x98\x80"), vec![0..4]);
+        assert_eq!(ranges(&regex("x.+"), b"x\xFF\n"), vec![0..2]);
+    }
+
+    #[test]
+    fn test_assertion() {
+        let regex = Regex::new(r"\b\w+\b").unwrap();
+
+        assert_eq!(ranges(&regex, b"ab\xFFcd ef"), vec![0..2, 3..5, 6..8]);
+
+        let regex = RegexBuilder::new(r"(?m)^.*$").build_bytes().unwrap();
+
+        assert_eq!(ranges(&regex, b"ab\ncd"), vec![0..2, 3..5]);
+    }
+}
@@ -1,5 +1,14 @@
-use crate::nondeterministic_finite_automaton::Automaton as NFA;
-use std::collections::{HashMap, HashSet};
+use crate::{
+    format::{escape_dot, format_dot_node, format_ranges},
+    nondeterministic_finite_automaton::{extend_unique, Automaton as NFA},
+    Budget, LimitError,
+};
+use parser::{decrement, increment, is_word_char, Class};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    ops::Range,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 enum Next {
@@ -19,7 +28,7 @@ impl Next {
 #[derive(Debug, Clone, PartialEq)]
 struct Context {
     current: usize,
-    transitions: HashMap<Vec<usize>, usize>,
+    transitions: HashMap<(Vec<usize>, Look), usize>,
 }
 
 impl Context {
@@ -30,92 +39,1022 @@ impl Context {
         }
     }
 
-    fn next(&mut self, destinations: &HashSet<usize>) -> Next {
-        let mut destinations = Vec::from_iter(destinations.clone());
-        destinations.sort();
+    fn next(&mut self, destinations: &[usize], behind: Look) -> Next {
+        let key = (destinations.to_vec(), behind);
 
-        match self.transitions.get(&destinations) {
+        match self.transitions.get(&key) {
             Some(destination) => Next::Contains(*destination),
             None => {
                 let current = self.current;
                 self.current += 1;
-                self.transitions.insert(destinations, current);
+                self.transitions.insert(key, current);
                 Next::New(current)
             }
         }
     }
 }
 
+/// What an assertion can tell about the char on one side of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Look {
+    /// The start or the end of the text.
+    Boundary,
+    Newline,
+    /// An ASCII word char, which is a word char in Unicode mode too.
+    Word,
+    /// A word char only in Unicode mode.
+    UnicodeWord,
+    Other,
+}
+
+impl Look {
+    pub(crate) const ALL: [Self; 5] = [
+        Self::Boundary,
+        Self::Newline,
+        Self::Word,
+        Self::UnicodeWord,
+        Self::Other,
+    ];
+
+    pub(crate) fn of(c: Option<char>) -> Self {
+        match c {
+            None => Self::Boundary,
+            Some('\n') => Self::Newline,
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' => Self::Word,
+            Some(c) if is_word_char(c) => Self::UnicodeWord,
+            Some(_) => Self::Other,
+        }
+    }
+
+    /// Like `of`, but for a byte, which is a word char only if it is an ASCII one.
+    pub(crate) fn of_byte(b: Option<u8>) -> Self {
+        match b {
+            Some(b) if !b.is_ascii() => Self::Other,
+            b => Self::of(b.map(char::from)),
+        }
+    }
+
+    fn representative(&self) -> Option<char> {
+        match self {
+            Self::Boundary => None,
+            Self::Newline => Some('\n'),
+            Self::Word => Some('a'),
+            Self::UnicodeWord => Some('é'),
+            Self::Other => Some(' '),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MatchKind {
+    /// Accepts every text in the language of the NFA.
+    All,
+    /// Keeps only the threads that a backtracking matcher would prefer over
+    /// the first accepting one, so that the longest accepted prefix is the
+    /// leftmost-first (Perl-like) match.
+    LeftmostFirst,
+}
+
+impl MatchKind {
+    pub(crate) fn arrange(&self, nfa: &NFA, mut destinations: Vec<usize>) -> Vec<usize> {
+        match self {
+            Self::All => {
+                destinations.sort();
+            }
+            Self::LeftmostFirst => {
+                if let Some(position) = destinations
+                    .iter()
+                    .position(|destination| nfa.accepts.contains(destination))
+                {
+                    destinations.truncate(position + 1);
+                }
+            }
+        }
+
+        destinations
+    }
+}
+
+pub(crate) type StateId = usize;
+
+/// The destination of the missing transitions.
+pub(crate) const DEAD: StateId = StateId::MAX;
+
+/// A deterministic finite automaton, which a `RegexBuilder` builds from a pattern.
+//
+// The chars are split into disjoint intervals at `boundaries`, and the intervals that every
+// state treats alike share an equivalence class in `classes`. The transitions are a dense
+// table with a row per state and a column per class, indexed by `state * class_count + class`.
+// The chars below U+0100, which are all the symbols of an automaton of bytes, look up their
+// class in `byte_classes` directly rather than searching the boundaries.
+//
+// When the NFA has assertions, a state also remembers the kind of the last char read,
+// and `starts` holds the start state for each kind of char preceding the search. A state
+// in `conditional_accepts` accepts only if the next char (or the end of the text) is of
+// the given kind.
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Automaton {
-    pub(crate) start: usize,
-    pub(crate) accepts: HashSet<usize>,
-    pub(crate) transitions: HashMap<(usize, char), usize>,
+pub struct Automaton {
+    pub(crate) start: StateId,
+    pub(crate) starts: HashMap<Look, StateId>,
+    pub(crate) accepts: HashSet<StateId>,
+    pub(crate) conditional_accepts: HashSet<(StateId, Look)>,
+    pub(crate) boundaries: Vec<char>,
+    pub(crate) classes: Vec<usize>,
+    pub(crate) byte_classes: Vec<usize>,
+    pub(crate) class_count: usize,
+    pub(crate) transitions: Vec<StateId>,
+    pub(crate) bytes: bool,
 }
 
 impl Automaton {
-    pub(crate) fn minimize(&mut self) {
-        // TODO
+    /// Merges the equivalent states and drops those from which nothing can be matched.
+    pub fn minimize(&mut self) {
+        let live_states = self.calc_live_states();
+
+        for (index, destination) in self.transitions.iter_mut().enumerate() {
+            if !live_states.contains(&(index / self.class_count))
+                || !live_states.contains(destination)
+            {
+                *destination = DEAD;
+            }
+        }
+
+        // Start states are kept even when nothing can be matched from them.
+        let mut states = live_states;
+        states.insert(self.start);
+        states.extend(self.starts.values());
+
+        let blocks = self.calc_equivalent_blocks(&states);
+        self.renumber(&blocks);
+        self.merge_classes();
+    }
+
+    fn row(&self, state: StateId) -> &[StateId] {
+        &self.transitions[state * self.class_count..(state + 1) * self.class_count]
+    }
+
+    fn calc_live_states(&self) -> HashSet<StateId> {
+        let mut reverse_transitions: HashMap<StateId, Vec<StateId>> = HashMap::new();
+
+        for (index, destination) in self.transitions.iter().enumerate() {
+            if *destination != DEAD {
+                reverse_transitions
+                    .entry(*destination)
+                    .or_default()
+                    .push(index / self.class_count);
+            }
+        }
+
+        let mut live_states = self.accepts.clone();
+        live_states.extend(self.conditional_accepts.iter().map(|(state, _)| *state));
+        let mut stack = Vec::from_iter(live_states.iter().cloned());
+
+        while let Some(destination) = stack.pop() {
+            for from in reverse_transitions.get(&destination).into_iter().flatten() {
+                if live_states.insert(*from) {
+                    stack.push(*from);
+                }
+            }
+        }
+
+        live_states
+    }
+
+    // Hopcroft's algorithm. Missing transitions go to an implicit dead state, which
+    // is never equivalent to a live state.
+    fn calc_equivalent_blocks(&self, states: &HashSet<StateId>) -> HashMap<StateId, usize> {
+        let mut states = Vec::from_iter(states.iter().cloned());
+        states.sort();
+
+        let indices = states
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (*state, index))
+            .collect::<HashMap<_, _>>();
+        let dead = states.len();
+
+        let mut reverse_transitions = vec![HashMap::<usize, Vec<usize>>::new(); self.class_count];
+
+        for (from, state) in states.iter().enumerate() {
+            for (class, destination) in self.row(*state).iter().enumerate() {
+                let destination = indices.get(destination).cloned().unwrap_or(dead);
+                reverse_transitions[class]
+                    .entry(destination)
+                    .or_default()
+                    .push(from);
+            }
+        }
+
+        for reverse_transitions in &mut reverse_transitions {
+            reverse_transitions.entry(dead).or_default().push(dead);
+        }
+
+        let mut outputs: BTreeMap<(bool, Vec<Look>), Vec<usize>> = BTreeMap::new();
+
+        for index in 0..=dead {
+            let output = match states.get(index) {
+                Some(state) => (
+                    self.accepts.contains(state),
+                    Look::ALL
+                        .into_iter()
+                        .filter(|look| self.conditional_accepts.contains(&(*state, *look)))
+                        .collect(),
+                ),
+                None => (false, Vec::new()),
+            };
+            outputs.entry(output).or_default().push(index);
+        }
+
+        let mut blocks = Vec::from_iter(outputs.into_values());
+
+        let mut block_of = vec![0; dead + 1];
+
+        for (block, members) in blocks.iter().enumerate() {
+            for member in members {
+                block_of[*member] = block;
+            }
+        }
+
+        let mut queued = vec![true; blocks.len()];
+        let mut queue = Vec::from_iter(0..blocks.len());
+
+        while let Some(splitter) = queue.pop() {
+            queued[splitter] = false;
+            let members = blocks[splitter].clone();
+
+            for reverse_transitions in &reverse_transitions {
+                let mut touched: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+                for member in &members {
+                    for from in reverse_transitions.get(member).into_iter().flatten() {
+                        touched.entry(block_of[*from]).or_default().push(*from);
+                    }
+                }
+
+                for (block, inside) in touched {
+                    if inside.len() == blocks[block].len() {
+                        continue;
+                    }
+
+                    let new_block = blocks.len();
+
+                    for member in &inside {
+                        block_of[*member] = new_block;
+                    }
+
+                    blocks[block].retain(|member| block_of[*member] == block);
+                    blocks.push(inside);
+
+                    if queued[block] || blocks[new_block].len() <= blocks[block].len() {
+                        queued.push(true);
+                        queue.push(new_block);
+                    } else {
+                        queued.push(false);
+                        queued[block] = true;
+                        queue.push(block);
+                    }
+                }
+            }
+        }
+
+        states
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (*state, block_of[index]))
+            .collect()
+    }
+
+    fn renumber(&mut self, blocks: &HashMap<StateId, usize>) {
+        let mut numbers = HashMap::new();
+        let mut queue = VecDeque::new();
+        let mut transitions = Vec::new();
+
+        for start in [self.start].iter().chain(self.starts.values()) {
+            let next = numbers.len();
+            numbers.entry(blocks[start]).or_insert_with(|| {
+                queue.push_back(*start);
+                next
+            });
+        }
+
+        // The states are numbered in the order they are dequeued, so each row is appended.
+        while let Some(from) = queue.pop_front() {
+            for destination in self.row(from) {
+                if *destination == DEAD {
+                    transitions.push(DEAD);
+                    continue;
+                }
+
+                let next = numbers.len();
+                let number = *numbers.entry(blocks[destination]).or_insert_with(|| {
+                    queue.push_back(*destination);
+                    next
+                });
+
+                transitions.push(number);
+            }
+        }
+
+        self.start = 0;
+        self.starts = self
+            .starts
+            .iter()
+            .map(|(behind, start)| (*behind, numbers[&blocks[start]]))
+            .collect();
+        self.accepts = self
+            .accepts
+            .iter()
+            .map(|accept| numbers[&blocks[accept]])
+            .collect();
+        self.conditional_accepts = self
+            .conditional_accepts
+            .iter()
+            .map(|(accept, ahead)| (numbers[&blocks[accept]], *ahead))
+            .collect();
+        self.transitions = transitions;
+    }
+
+    // Merging states can leave classes that no state tells apart any more.
+    fn merge_classes(&mut self) {
+        let states = self.transitions.len() / self.class_count;
+        let rows = (0..states).map(|state| {
+            self.row(state)
+                .iter()
+                .cloned()
+                .enumerate()
+                .filter(|(_, destination)| *destination != DEAD)
+        });
+        let (merged, class_count) = calc_classes(self.class_count, rows);
+
+        let mut representatives = vec![0; class_count];
+
+        for (class, merged) in merged.iter().enumerate().rev() {
+            representatives[*merged] = class;
+        }
+
+        self.transitions = (0..states)
+            .flat_map(|state| {
+                let row = self.row(state);
+                representatives.iter().map(|class| row[*class])
+            })
+            .collect();
+        self.classes = self.classes.iter().map(|class| merged[*class]).collect();
+        self.byte_classes = self
+            .byte_classes
+            .iter()
+            .map(|class| merged[*class])
+            .collect();
+        self.class_count = class_count;
     }
 }
 
-impl From<NFA> for Automaton {
-    fn from(nfa: NFA) -> Self {
+impl Automaton {
+    /// Builds the automaton by subset construction, failing once it has more than `limit`
+    /// states or has spent the budget.
+    pub(crate) fn new(
+        nfa: &NFA,
+        kind: MatchKind,
+        limit: usize,
+        budget: &mut Budget,
+    ) -> Result<Self, LimitError> {
+        let has_assertions = !nfa.assertions.is_empty();
+        let (boundaries, looks) = calc_alphabet(nfa);
+        let (behinds, aheads) = match has_assertions {
+            true => (&Look::ALL[..], &Look::ALL[1..]),
+            false => (&[Look::Boundary][..], &[Look::Other][..]),
+        };
+        let alphabet = Alphabet::new(nfa, &boundaries, &looks, budget)?;
+
         let mut context = Context::new();
-        let mut queue = vec![[nfa.start].into()];
+        let start_destinations = kind.arrange(nfa, nfa.calc_epsilon_closure(nfa.start));
+        let mut queue = Vec::new();
+        let mut starts = HashMap::new();
 
-        let start = context.next(&[nfa.start].into()).unwrap();
-        let mut accepts = HashSet::new();
-        let mut transitions = HashMap::new();
+        for behind in behinds {
+            if let Next::New(start) = context.next(&start_destinations, *behind) {
+                queue.push((start_destinations.clone(), *behind));
 
-        while let Some(destinations) = queue.pop() {
-            if !nfa.accepts.is_disjoint(&destinations) {
-                accepts.insert(context.next(&destinations).unwrap());
+                if has_assertions {
+                    starts.insert(*behind, start);
+                }
             }
+        }
+
+        let start = context.next(&start_destinations, Look::Boundary).unwrap();
+        let mut accepts = HashSet::new();
+        let mut conditional_accepts = HashSet::new();
+        let mut closures = HashMap::new();
+        let mut rows = Vec::new();
 
-            let chars = nfa.calc_chars_without_epsilon_transitions(Some(&destinations));
-            let mut chars = Vec::from_iter(chars);
-            chars.sort();
+        while let Some((destinations, behind)) = queue.pop() {
+            let from = context.next(&destinations, behind).unwrap();
+            rows.resize_with(context.current, Vec::new);
+            budget.spend(destinations.len() + alphabet.class_count)?;
+            let resolve = |ahead| resolve(nfa, kind, &destinations, behind, ahead);
 
-            for c in chars {
-                let from = context.next(&destinations).unwrap();
-                let destination = {
-                    let destinations = destinations
+            // Without assertions, what follows does not matter.
+            let looks = match has_assertions {
+                true => &Look::ALL[..],
+                false => &Look::ALL[..1],
+            };
+            let accepting = looks
+                .iter()
+                .copied()
+                .filter(|ahead| {
+                    resolve(*ahead)
                         .iter()
-                        .flat_map(|destination| nfa.calc_destinations(*destination, Some(c)))
-                        .collect::<HashSet<_>>();
+                        .any(|destination| nfa.accepts.contains(destination))
+                })
+                .collect::<Vec<_>>();
 
-                    if destinations.is_empty() {
-                        continue;
-                    }
+            if accepting.len() == looks.len() {
+                accepts.insert(from);
+            } else {
+                conditional_accepts.extend(accepting.into_iter().map(|ahead| (from, ahead)));
+            }
+
+            // The NFA states reached by each class, in priority order, with a set to keep
+            // them unique.
+            let mut next_destinations: BTreeMap<usize, (Vec<usize>, HashSet<usize>)> =
+                BTreeMap::new();
+
+            for ahead in aheads {
+                let resolved = resolve(*ahead);
+
+                // Without assertions, the states resolved are those already counted.
+                if has_assertions {
+                    budget.spend(resolved.len())?;
+                }
+
+                for destination in resolved {
+                    for (class, nexts) in alphabet.transitions(destination) {
+                        if alphabet.looks[*class] != *ahead {
+                            continue;
+                        }
 
-                    match context.next(&destinations) {
-                        Next::New(destination) => {
-                            queue.push(destinations);
-                            destination
+                        let (ordered, seen) = next_destinations.entry(*class).or_default();
+
+                        for next in nexts {
+                            let closure = closures
+                                .entry(*next)
+                                .or_insert_with(|| nfa.calc_epsilon_closure(*next));
+                            budget.spend(closure.len())?;
+                            ordered.extend(closure.iter().filter(|state| seen.insert(**state)));
                         }
-                        Next::Contains(destination) => destination,
                     }
+                }
+            }
+
+            for (class, (next_destinations, _)) in next_destinations {
+                let next_destinations = kind.arrange(nfa, next_destinations);
+                let behind = match has_assertions {
+                    true => alphabet.looks[class],
+                    false => Look::Boundary,
+                };
+                let destination = match context.next(&next_destinations, behind) {
+                    Next::New(destination) if destination >= limit => {
+                        return Err(LimitError::DfaStates { limit });
+                    }
+                    Next::New(destination) => {
+                        queue.push((next_destinations, behind));
+                        destination
+                    }
+                    Next::Contains(destination) => destination,
                 };
 
-                transitions.entry((from, c)).or_insert(destination);
+                rows[from].push((class, destination));
             }
         }
 
-        Self {
+        let mut transitions = vec![DEAD; rows.len() * alphabet.class_count];
+
+        for (from, row) in rows.iter().enumerate() {
+            for (class, destination) in row {
+                transitions[from * alphabet.class_count + class] = *destination;
+            }
+        }
+
+        let mut automaton = Self {
             start,
+            starts,
             accepts,
+            conditional_accepts,
+            byte_classes: calc_byte_classes(&boundaries, &alphabet.classes),
+            boundaries,
+            classes: alphabet.classes,
+            class_count: alphabet.class_count,
             transitions,
+            bytes: nfa.bytes,
+        };
+
+        // The classes of the NFA can be finer than the automaton needs.
+        automaton.merge_classes();
+        Ok(automaton)
+    }
+
+    pub(crate) fn start(&self, behind: Look) -> usize {
+        self.starts.get(&behind).cloned().unwrap_or(self.start)
+    }
+
+    pub(crate) fn is_accepting(&self, state: usize, ahead: Look) -> bool {
+        self.accepts.contains(&state) || self.conditional_accepts.contains(&(state, ahead))
+    }
+
+    pub(crate) fn next(&self, from: StateId, c: char) -> Option<StateId> {
+        let class = match self.byte_classes.get(c as usize) {
+            Some(class) => *class,
+            None => {
+                let interval = self.boundaries.partition_point(|boundary| *boundary <= c) - 1;
+                self.classes[interval]
+            }
+        };
+        let destination = self.transitions[from * self.class_count + class];
+        (destination != DEAD).then_some(destination)
+    }
+
+    /// Returns the end of the longest accepted prefix of `text[start..]`, seeing the
+    /// char before `start` as assertions do.
+    pub(crate) fn longest_prefix(&self, text: &str, start: usize) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+
+        self.scan(text[..start].chars().next_back(), chars, text.len(), None)
+    }
+
+    /// Returns the start of the longest suffix of `text[min..end]` that an automaton built
+    /// from a reversed NFA accepts, reading the text backwards from `end` and seeing the
+    /// chars around it as assertions do.
+    pub(crate) fn longest_suffix(&self, text: &str, min: usize, end: usize) -> Option<usize> {
+        let chars = text[min..end]
+            .char_indices()
+            .rev()
+            .map(|(i, c)| (min + i + c.len_utf8(), c));
+
+        self.scan(
+            text[end..].chars().next(),
+            chars,
+            min,
+            text[..min].chars().next_back(),
+        )
+    }
+
+    /// Like `longest_prefix`, for an automaton built from an NFA that reads bytes.
+    pub(crate) fn longest_prefix_bytes(&self, text: &[u8], start: usize) -> Option<usize> {
+        let bytes = text[start..]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (start + i, char::from(*b)));
+        let behind = start.checked_sub(1).map(|i| char::from(text[i]));
+
+        self.scan(behind, bytes, text.len(), None)
+    }
+
+    /// Like `longest_suffix`, for an automaton built from an NFA that reads bytes.
+    pub(crate) fn longest_suffix_bytes(
+        &self,
+        text: &[u8],
+        min: usize,
+        end: usize,
+    ) -> Option<usize> {
+        let bytes = text[min..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, b)| (min + i + 1, char::from(*b)));
+        let after = min.checked_sub(1).map(|i| char::from(text[i]));
+
+        self.scan(text.get(end).map(|b| char::from(*b)), bytes, min, after)
+    }
+
+    // Runs the automaton over `chars`, each paired with the position before it in the order
+    // they are read, starting after the char `behind`. Returns the last position where it
+    // accepted, which is `last`, followed by the char `after`, once every char is read.
+    fn scan(
+        &self,
+        behind: Option<char>,
+        chars: impl Iterator<Item = (usize, char)>,
+        last: usize,
+        after: Option<char>,
+    ) -> Option<usize> {
+        let mut current = self.start(self.look(behind));
+        let mut end = None;
+
+        for (position, c) in chars {
+            crate::count_step();
+
+            if self.is_accepting(current, self.look(Some(c))) {
+                end = Some(position);
+            }
+
+            match self.next(current, c) {
+                Some(destination) => current = destination,
+                None => return end,
+            }
+        }
+
+        if self.is_accepting(current, self.look(after)) {
+            end = Some(last);
+        }
+
+        end
+    }
+
+    fn look(&self, c: Option<char>) -> Look {
+        match self.bytes {
+            true => Look::of_byte(c.map(|c| c as u8)),
+            false => Look::of(c),
         }
     }
 }
 
+/// Splits the chars into the intervals that the NFA cannot tell apart, returning the first
+/// char of each interval and the look of its chars. With assertions, `\n` and the word chars
+/// get intervals of their own, where only the ASCII ones are word chars for bytes.
+pub(crate) fn calc_alphabet(nfa: &NFA) -> (Vec<char>, Vec<Look>) {
+    let has_assertions = !nfa.assertions.is_empty();
+    let mut ranges = nfa.calc_ranges_without_epsilon_transitions(None);
+
+    if has_assertions {
+        ranges.insert(('\n', '\n'));
+        ranges.extend(Class::word(false).ranges());
+
+        if !nfa.bytes {
+            ranges.extend(Class::word(true).ranges());
+        }
+    }
+
+    let boundaries = calc_boundaries(&ranges);
+    let looks = boundaries
+        .iter()
+        .map(|c| match has_assertions {
+            true if nfa.bytes && !c.is_ascii() => Look::Other,
+            true => Look::of(Some(*c)),
+            false => Look::Other,
+        })
+        .collect();
+
+    (boundaries, looks)
+}
+
+/// Returns the intervals that the chars of the range fall in.
+pub(crate) fn calc_intervals(boundaries: &[char], (start, end): (char, char)) -> Range<usize> {
+    let first = boundaries.partition_point(|boundary| *boundary < start);
+    let last = boundaries.partition_point(|boundary| *boundary <= end);
+    first..last
+}
+
+/// Returns the class of each char below U+0100, given the classes of the intervals.
+fn calc_byte_classes(boundaries: &[char], classes: &[usize]) -> Vec<usize> {
+    (0..=u8::MAX)
+        .map(|b| classes[boundaries.partition_point(|boundary| *boundary <= char::from(b)) - 1])
+        .collect()
+}
+
+/// The intervals of chars grouped into the classes that the NFA cannot tell apart, with the
+/// char transitions of each NFA state by class.
+struct Alphabet {
+    classes: Vec<usize>,
+    class_count: usize,
+    looks: Vec<Look>,
+    transitions: HashMap<usize, Vec<(usize, Vec<usize>)>>,
+}
+
+impl Alphabet {
+    fn new(
+        nfa: &NFA,
+        boundaries: &[char],
+        looks: &[Look],
+        budget: &mut Budget,
+    ) -> Result<Self, LimitError> {
+        let intervals = |range| calc_intervals(boundaries, range);
+
+        // The chars that lead an NFA state to the same states, and the look of each
+        // interval, split the intervals.
+        let mut range_sets = HashSet::new();
+
+        for nfa_transitions in nfa.transitions.values() {
+            let mut by_destinations: HashMap<&[usize], Vec<(char, char)>> = HashMap::new();
+
+            for (range, destinations) in nfa_transitions {
+                if let Some(range) = range {
+                    by_destinations
+                        .entry(destinations)
+                        .or_default()
+                        .push(*range);
+                }
+            }
+
+            for mut ranges in by_destinations.into_values() {
+                ranges.sort();
+                range_sets.insert(ranges);
+            }
+        }
+
+        let rows = range_sets
+            .iter()
+            .map(|ranges| {
+                ranges
+                    .iter()
+                    .flat_map(|range| intervals(*range))
+                    .map(|interval| (interval, 0))
+                    .collect()
+            })
+            .chain([looks
+                .iter()
+                .map(|look| *look as usize)
+                .enumerate()
+                .collect()]);
+        let (classes, class_count) = calc_classes(boundaries.len(), rows.map(Vec::into_iter));
+
+        let mut class_looks = vec![Look::Other; class_count];
+
+        for (interval, class) in classes.iter().enumerate() {
+            class_looks[*class] = looks[interval];
+        }
+
+        let mut transitions = HashMap::new();
+
+        for (from, nfa_transitions) in &nfa.transitions {
+            let mut ranges = Vec::from_iter(nfa_transitions.keys().flatten());
+            ranges.sort();
+
+            let mut by_class: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+            for range in ranges {
+                let destinations = nfa.calc_destinations(*from, Some(*range));
+                let range_intervals = intervals(*range);
+                budget.spend(range_intervals.len())?;
+
+                let range_classes = range_intervals
+                    .map(|interval| classes[interval])
+                    .collect::<BTreeSet<_>>();
+
+                for class in range_classes {
+                    extend_unique(
+                        by_class.entry(class).or_default(),
+                        destinations.iter().cloned(),
+                    );
+                }
+            }
+
+            transitions.insert(*from, Vec::from_iter(by_class));
+        }
+
+        Ok(Self {
+            classes,
+            class_count,
+            looks: class_looks,
+            transitions,
+        })
+    }
+
+    /// Returns the classes read from an NFA state with the states they lead to, in the order
+    /// of the classes.
+    fn transitions(&self, from: usize) -> &[(usize, Vec<usize>)] {
+        self.transitions
+            .get(&from)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the NFA states reachable from `destinations` through the assertions that hold
+/// between a char of kind `behind` and one of kind `ahead`.
+pub(crate) fn resolve(
+    nfa: &NFA,
+    kind: MatchKind,
+    destinations: &[usize],
+    behind: Look,
+    ahead: Look,
+) -> Vec<usize> {
+    if nfa.assertions.is_empty() {
+        return destinations.to_vec();
+    }
+
+    let mut resolved = Vec::new();
+
+    for destination in destinations {
+        extend_unique(
+            &mut resolved,
+            nfa.calc_look_closure(
+                *destination,
+                behind.representative(),
+                ahead.representative(),
+            ),
+        );
+    }
+
+    kind.arrange(nfa, resolved)
+}
+
+/// Splits the symbols into classes by partition refinement, given the transitions of each
+/// state as `(symbol, destination)` pairs, missing symbols going to the dead state. Returns
+/// the class of each symbol, numbered in the order of their first symbols, and the number of
+/// classes.
+fn calc_classes<R>(symbols: usize, rows: impl Iterator<Item = R>) -> (Vec<usize>, usize)
+where
+    R: Iterator<Item = (usize, StateId)>,
+{
+    let mut classes = vec![0; symbols];
+    let mut sizes = vec![symbols];
+
+    for row in rows {
+        let mut groups: BTreeMap<(usize, StateId), Vec<usize>> = BTreeMap::new();
+
+        for (symbol, destination) in row {
+            groups
+                .entry((classes[symbol], destination))
+                .or_default()
+                .push(symbol);
+        }
+
+        for ((class, _), members) in groups {
+            if members.len() == sizes[class] {
+                continue;
+            }
+
+            sizes[class] -= members.len();
+            sizes.push(members.len());
+
+            for member in members {
+                classes[member] = sizes.len() - 1;
+            }
+        }
+    }
+
+    let mut numbers = HashMap::new();
+
+    for class in &mut classes {
+        let next = numbers.len();
+        *class = *numbers.entry(*class).or_insert(next);
+    }
+
+    (classes, numbers.len())
+}
+
+fn calc_boundaries(ranges: &HashSet<(char, char)>) -> Vec<char> {
+    let mut boundaries = BTreeSet::from(['\0']);
+
+    for (start, end) in ranges {
+        boundaries.insert(*start);
+
+        if let Some(next) = increment(*end) {
+            boundaries.insert(next);
+        }
+    }
+
+    Vec::from_iter(boundaries)
+}
+
+impl Automaton {
+    /// Returns the automaton in the DOT language of Graphviz. The start states are drawn as
+    /// boxes and the accept states with a double outline, which is dashed for a state that
+    /// accepts only before some kinds of chars, listed in its label. The transitions to a
+    /// destination are drawn as an edge labelled with the class of their chars.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph DFA {\n    rankdir=LR;\n    node [shape=circle];\n");
+
+        for state in 0..self.transitions.len() / self.class_count {
+            let mut attributes = Vec::new();
+            let looks = self.conditional_looks(state);
+
+            if state == self.start || self.starts.values().any(|start| *start == state) {
+                attributes.push("shape=box".to_string());
+            }
+
+            if self.accepts.contains(&state) {
+                attributes.push("peripheries=2".to_string());
+            } else if !looks.is_empty() {
+                attributes.push("peripheries=2, style=dashed".to_string());
+                attributes.push(format!(r#"label="{state}\n{}""#, looks.join(", ")));
+            }
+
+            dot.push_str(&format_dot_node(state, &attributes));
+        }
+
+        for from in 0..self.transitions.len() / self.class_count {
+            for (ranges, destination) in self.char_transitions(from) {
+                let label = escape_dot(&format_ranges(&ranges, self.bytes));
+                dot.push_str(&format!(
+                    "    {from} -> {destination} [label=\"{label}\"];\n"
+                ));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Returns the kinds of chars before which a conditionally accepting state accepts.
+    fn conditional_looks(&self, state: StateId) -> Vec<String> {
+        Look::ALL
+            .iter()
+            .filter(|look| self.conditional_accepts.contains(&(state, **look)))
+            .map(|look| format!("{look:?}"))
+            .collect()
+    }
+
+    // Returns the transitions from a state with their ranges grouped by destination, in the
+    // order of the destinations.
+    fn char_transitions(&self, from: StateId) -> Vec<(Vec<(char, char)>, StateId)> {
+        let row = self.row(from);
+        let mut ranges: BTreeMap<StateId, Vec<(char, char)>> = BTreeMap::new();
+
+        for (interval, start) in self.boundaries.iter().enumerate() {
+            let destination = row[self.classes[interval]];
+
+            if destination == DEAD {
+                continue;
+            }
+
+            let end = match self.boundaries.get(interval + 1) {
+                Some(next) => decrement(*next).unwrap(),
+                None => char::MAX,
+            };
+
+            ranges.entry(destination).or_default().push((*start, end));
+        }
+
+        ranges
+            .into_iter()
+            .map(|(destination, ranges)| (ranges, destination))
+            .collect()
+    }
+}
+
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut accepts = Vec::from_iter(&self.accepts);
+        accepts.sort();
+
+        writeln!(f, "start: {}", self.start)?;
+
+        if !self.starts.is_empty() {
+            let starts = Look::ALL
+                .iter()
+                .filter_map(|look| Some(format!("{look:?} => {}", self.starts.get(look)?)))
+                .collect::<Vec<_>>();
+
+            writeln!(f, "starts: {}", starts.join(", "))?;
+        }
+
+        writeln!(f, "accepts: {accepts:?}")?;
+
+        for from in 0..self.transitions.len() / self.class_count {
+            let looks = self.conditional_looks(from);
+
+            match looks.is_empty() {
+                true => writeln!(f, "{from}:")?,
+                false => writeln!(f, "{from}: accepts before {}", looks.join(", "))?,
+            }
+
+            for (ranges, destination) in self.char_transitions(from) {
+                writeln!(
+                    f,
+                    "  {} => {destination}",
+                    format_ranges(&ranges, self.bytes)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<NFA> for Automaton {
+    fn from(nfa: NFA) -> Self {
+        Self::new(
+            &nfa,
+            MatchKind::All,
+            usize::MAX,
+            &mut Budget::new(usize::MAX),
+        )
+        .unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        deterministic_finite_automaton::Automaton,
+        deterministic_finite_automaton::{calc_byte_classes, Automaton, Look, MatchKind, DEAD},
         nondeterministic_finite_automaton::Automaton as NFA,
+        Budget,
     };
+    use parser::parse;
+
+    const D: usize = DEAD;
+
+    fn minimized(pattern: &str) -> Automaton {
+        let mut nfa = NFA::from(parse(pattern).unwrap());
+        nfa.remove_epsilon_transitions();
+
+        let mut dfa = Automaton::from(nfa);
+        dfa.minimize();
+        dfa
+    }
+
+    fn count_states(automaton: &Automaton) -> usize {
+        automaton.transitions.len() / automaton.class_count
+    }
 
     #[test]
     fn test_from_nfa() {
@@ -126,30 +1065,297 @@ mod tests {
                 transitions: [
                     (
                         0,
-                        [(Some('a'), [0, 2].into()), (Some('b'), [1].into())].into(),
+                        [
+                            (Some(('a', 'a')), [0, 2].into()),
+                            (Some(('b', 'b')), [1].into())
+                        ]
+                        .into(),
                     ),
                     (
                         1,
-                        [(Some('a'), [2].into()), (Some('b'), [1, 2].into())].into(),
+                        [
+                            (Some(('a', 'a')), [2].into()),
+                            (Some(('b', 'b')), [1, 2].into())
+                        ]
+                        .into(),
                     ),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
             }),
             Automaton {
                 start: 0,
+                starts: [].into(),
                 accepts: [1, 3, 4].into(),
-                transitions: [
-                    ((0, 'a'), 1),
-                    ((0, 'b'), 2),
-                    ((1, 'a'), 1),
-                    ((1, 'b'), 2),
-                    ((2, 'a'), 3),
-                    ((2, 'b'), 4),
-                    ((4, 'a'), 3),
-                    ((4, 'b'), 4),
-                ]
-                .into(),
+                conditional_accepts: [].into(),
+                boundaries: vec!['\0', 'a', 'b', 'c'],
+                classes: vec![0, 1, 2, 0],
+                byte_classes: calc_byte_classes(&['\0', 'a', 'b', 'c'], &[0, 1, 2, 0]),
+                class_count: 3,
+                #[rustfmt::skip]
+                transitions: vec![
+                    D, 1, 2,
+                    D, 1, 2,
+                    D, 3, 4,
+                    D, D, D,
+                    D, 3, 4,
+                ],
+                bytes: false,
+            },
+        );
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut automaton = Automaton {
+            start: 0,
+            starts: [].into(),
+            accepts: [2, 3].into(),
+            conditional_accepts: [].into(),
+            boundaries: vec!['\0', 'a', 'b', 'c', 'd'],
+            classes: vec![0, 1, 2, 3, 0],
+            byte_classes: calc_byte_classes(&['\0', 'a', 'b', 'c', 'd'], &[0, 1, 2, 3, 0]),
+            class_count: 4,
+            #[rustfmt::skip]
+            transitions: vec![
+                D, 1, D, 4,
+                D, D, 2, 3,
+                D, D, D, D,
+                D, D, D, D,
+                D, 4, D, D,
+            ],
+            bytes: false,
+        };
+        automaton.minimize();
+
+        assert_eq!(
+            automaton,
+            Automaton {
+                start: 0,
+                starts: [].into(),
+                accepts: [2].into(),
+                conditional_accepts: [].into(),
+                boundaries: vec!['\0', 'a', 'b', 'c', 'd'],
+                classes: vec![0, 1, 2, 2, 0],
+                byte_classes: calc_byte_classes(&['\0', 'a', 'b', 'c', 'd'], &[0, 1, 2, 2, 0]),
+                class_count: 3,
+                #[rustfmt::skip]
+                transitions: vec![
+                    D, 1, D,
+                    D, D, 2,
+                    D, D, D,
+                ],
+                bytes: false,
+            },
+        );
+
+        let mut automaton = Automaton {
+            start: 0,
+            starts: [].into(),
+            accepts: [].into(),
+            conditional_accepts: [].into(),
+            boundaries: vec!['\0', 'a', 'b', 'c', 'd'],
+            classes: vec![0, 1, 0, 0, 0],
+            byte_classes: calc_byte_classes(&['\0', 'a', 'b', 'c', 'd'], &[0, 1, 0, 0, 0]),
+            class_count: 2,
+            transitions: vec![D, 1, D, D],
+            bytes: false,
+        };
+        automaton.minimize();
+
+        assert_eq!(
+            automaton,
+            Automaton {
+                start: 0,
+                starts: [].into(),
+                accepts: [].into(),
+                conditional_accepts: [].into(),
+                boundaries: vec!['\0', 'a', 'b', 'c', 'd'],
+                classes: vec![0, 0, 0, 0, 0],
+                byte_classes: calc_byte_classes(&['\0', 'a', 'b', 'c', 'd'], &[0, 0, 0, 0, 0]),
+                class_count: 1,
+                transitions: vec![D],
+                bytes: false,
             },
         );
+
+        assert_eq!(count_states(&minimized("(a|b)*")), 1);
+        assert_eq!(count_states(&minimized("(a*b*)*")), 1);
+        assert_eq!(minimized("(a|b)*"), minimized("(a*b*)*"));
+
+        assert_eq!(minimized("a(b|c)d|a(c|b)d"), minimized("a(b|c)d"));
+        assert_eq!(count_states(&minimized("a*a*a*")), 1);
+        assert_eq!(count_states(&minimized("(a|b)*a(a|b)(a|b)")), 8);
+    }
+
+    #[test]
+    fn test_classes() {
+        let automaton = Automaton::from(NFA::from(parse("[0-9]+[a-z]").unwrap()));
+
+        assert_eq!(automaton.boundaries, vec!['\0', '0', ':', 'a', '{']);
+        assert_eq!(automaton.classes, vec![0, 1, 0, 2, 0]);
+        assert_eq!(automaton.class_count, 3);
+        assert_eq!(automaton.byte_classes.len(), 256);
+        assert_eq!(
+            [b'\0', b'5', b':', b'q', b'{', 0xFF].map(|b| automaton.byte_classes[b as usize]),
+            [0, 1, 0, 2, 0, 0],
+        );
+
+        let automaton = minimized("[a-c]x|[d-f]x");
+
+        assert_eq!(automaton.boundaries, vec!['\0', 'a', 'd', 'g', 'x', 'y']);
+        assert_eq!(automaton.classes, vec![0, 1, 1, 0, 2, 0]);
+        assert_eq!(automaton.class_count, 3);
+        assert_eq!(automaton.longest_prefix("ex", 0), Some(2));
+        assert_eq!(automaton.longest_prefix("gx", 0), None);
+
+        // The hundreds of ranges of `\w` and `\s` each make a single class.
+        let automaton = Automaton::from(NFA::from(parse(r"(?:\w+\s*){3}").unwrap()));
+
+        assert!(automaton.boundaries.len() > 1_000);
+        assert_eq!(automaton.class_count, 3);
+        assert_eq!(automaton.longest_prefix("ab é\u{3000}ü9", 0), Some(11));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            minimized("ab|cb").to_string(),
+            "start: 0\naccepts: [2]\n0:\n  [ac] => 1\n1:\n  [b] => 2\n2:\n",
+        );
+        assert_eq!(
+            minimized(r"x\b").to_string(),
+            concat!(
+                "start: 0\n",
+                "starts: Boundary => 0, Newline => 0, Word => 0, UnicodeWord => 0, Other => 0\n",
+                "accepts: []\n",
+                "0:\n  [x] => 1\n",
+                "1: accepts before Boundary, Newline, Other\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            minimized("[a-c]x|dx|a").to_dot(),
+            concat!(
+                "digraph DFA {\n",
+                "    rankdir=LR;\n",
+                "    node [shape=circle];\n",
+                "    0 [shape=box];\n",
+                "    1 [peripheries=2];\n",
+                "    3 [peripheries=2];\n",
+                "    0 -> 1 [label=\"[a]\"];\n",
+                "    0 -> 2 [label=\"[b-d]\"];\n",
+                "    1 -> 3 [label=\"[x]\"];\n",
+                "    2 -> 3 [label=\"[x]\"];\n",
+                "}\n",
+            ),
+        );
+        assert_eq!(
+            minimized(r"x\b|y\z").to_dot(),
+            concat!(
+                "digraph DFA {\n",
+                "    rankdir=LR;\n",
+                "    node [shape=circle];\n",
+                "    0 [shape=box];\n",
+                "    1 [peripheries=2, style=dashed, label=\"1\\nBoundary, Newline, Other\"];\n",
+                "    2 [peripheries=2, style=dashed, label=\"2\\nBoundary\"];\n",
+                "    0 -> 1 [label=\"[x]\"];\n",
+                "    0 -> 2 [label=\"[y]\"];\n",
+                "}\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_longest_prefix() {
+        let nfa = NFA::from(parse("a|ab").unwrap());
+
+        assert_eq!(
+            Automaton::new(
+                &nfa,
+                MatchKind::All,
+                usize::MAX,
+                &mut Budget::new(usize::MAX)
+            )
+            .unwrap()
+            .longest_prefix("abc", 0),
+            Some(2)
+        );
+        assert_eq!(
+            Automaton::new(
+                &nfa,
+                MatchKind::LeftmostFirst,
+                usize::MAX,
+                &mut Budget::new(usize::MAX)
+            )
+            .unwrap()
+            .longest_prefix("abc", 0),
+            Some(1),
+        );
+
+        let nfa = NFA::from(parse("(a|b)*b").unwrap());
+
+        assert_eq!(
+            Automaton::new(
+                &nfa,
+                MatchKind::LeftmostFirst,
+                usize::MAX,
+                &mut Budget::new(usize::MAX)
+            )
+            .unwrap()
+            .longest_prefix("abab", 0),
+            Some(4),
+        );
+        assert_eq!(
+            Automaton::new(
+                &nfa,
+                MatchKind::LeftmostFirst,
+                usize::MAX,
+                &mut Budget::new(usize::MAX)
+            )
+            .unwrap()
+            .longest_prefix("aa", 0),
+            None,
+        );
+
+        let nfa = NFA::from(parse("[a-m]x|[f-z]y").unwrap());
+        let automaton = Automaton::new(
+            &nfa,
+            MatchKind::All,
+            usize::MAX,
+            &mut Budget::new(usize::MAX),
+        )
+        .unwrap();
+
+        assert_eq!(automaton.longest_prefix("gx", 0), Some(2));
+        assert_eq!(automaton.longest_prefix("gy", 0), Some(2));
+        assert_eq!(automaton.longest_prefix("by", 0), None);
+        assert_eq!(automaton.longest_prefix("zx", 0), None);
+    }
+
+    #[test]
+    fn test_assertion() {
+        let automaton = minimized(r"\ba");
+
+        assert_eq!(automaton.longest_prefix("a", 0), Some(1));
+        assert_eq!(automaton.longest_prefix(" a", 1), Some(2));
+        assert_eq!(automaton.longest_prefix("xa", 1), None);
+
+        let automaton = minimized(r"a\b");
+
+        assert_eq!(automaton.longest_prefix("a", 0), Some(1));
+        assert_eq!(automaton.longest_prefix("a b", 0), Some(1));
+        assert_eq!(automaton.longest_prefix("ab", 0), None);
+
+        let automaton = minimized(r"a$|ab");
+
+        assert!(automaton.conditional_accepts.contains(&(1, Look::Boundary)));
+        assert_eq!(automaton.longest_prefix("a", 0), Some(1));
+        assert_eq!(automaton.longest_prefix("ab", 0), Some(2));
+        assert_eq!(automaton.longest_prefix("ac", 0), None);
     }
 }
@@ -0,0 +1,77 @@
+use parser::{increment, Class};
+
+/// Formats the ranges as a class, such as `[a-cx]`. The chars of an automaton that reads
+/// bytes are written as bytes, such as `[\xC3]`.
+pub(crate) fn format_ranges(ranges: &[(char, char)], bytes: bool) -> String {
+    let class = Class::new(ranges.iter().cloned());
+
+    if !bytes {
+        return class.to_string();
+    }
+
+    let mut label = String::from("[");
+
+    for (start, end) in class.ranges() {
+        label.push_str(&format_byte(*start));
+
+        if start != end {
+            if increment(*start) != Some(*end) {
+                label.push('-');
+            }
+
+            label.push_str(&format_byte(*end));
+        }
+    }
+
+    label.push(']');
+    label
+}
+
+fn format_byte(c: char) -> String {
+    match c {
+        '[' | ']' | '-' | '^' | '\\' => format!("\\{c}"),
+        c if c.is_ascii_graphic() || c == ' ' => c.to_string(),
+        c => format!("\\x{:02X}", c as u32),
+    }
+}
+
+/// Joins the states with commas, such as `1, 3`.
+pub(crate) fn format_states<'a>(states: impl IntoIterator<Item = &'a usize>) -> String {
+    let states = states.into_iter().map(usize::to_string).collect::<Vec<_>>();
+    states.join(", ")
+}
+
+/// Escapes a string for a quoted ID of the DOT language.
+pub(crate) fn escape_dot(s: &str) -> String {
+    s.replace('\\', r"\\").replace('"', r#"\""#)
+}
+
+/// Formats the DOT statement of a node, or an empty string if it has no attributes.
+pub(crate) fn format_dot_node(state: usize, attributes: &[String]) -> String {
+    match attributes.is_empty() {
+        true => String::new(),
+        false => format!("    {state} [{}];\n", attributes.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{escape_dot, format_ranges, format_states};
+
+    #[test]
+    fn test_format_ranges() {
+        assert_eq!(format_ranges(&[('x', 'x'), ('a', 'c')], false), "[a-cx]");
+        assert_eq!(format_ranges(&[('é', 'é')], false), "[é]");
+        assert_eq!(format_ranges(&[('\u{C3}', '\u{C3}')], true), r"[\xC3]");
+        assert_eq!(
+            format_ranges(&[('\0', '\u{7F}'), ('-', '-')], true),
+            r"[\x00-\x7F]"
+        );
+        assert_eq!(
+            format_ranges(&[('-', '.'), ('\u{80}', '\u{BF}')], true),
+            r"[\-.\x80-\xBF]"
+        );
+        assert_eq!(format_states(&[1, 3]), "1, 3");
+        assert_eq!(escape_dot(r#"[\x00"]"#), r#"[\\x00\"]"#);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{
+    deterministic_finite_automaton::{calc_alphabet, calc_intervals, resolve, Look, MatchKind},
+    nondeterministic_finite_automaton::{extend_unique, Automaton as NFA},
+};
+use std::{collections::HashMap, ops::Range};
+
+// A search that clears the cache more often than this is thrashing, and simulates the NFA
+// for the rest of the text instead of making states only to throw them away.
+const MAX_CLEARS: usize = 3;
+
+// The char transitions of each NFA state, as disjoint runs of intervals, sorted so that the
+// run of an interval can be searched for, with the states that every interval of the run
+// leads to.
+type Transitions = HashMap<usize, Vec<(Range<usize>, Vec<usize>)>>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    destinations: Vec<usize>,
+    behind: Look,
+    accepting: Vec<Look>,
+}
+
+/// The states made so far. It is kept apart from the automaton so that searches can share
+/// the automaton while each owns a cache.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Cache {
+    states: Vec<State>,
+    ids: HashMap<(Vec<usize>, Look), usize>,
+    transitions: HashMap<(usize, usize), Option<usize>>,
+    clears: usize,
+}
+
+impl Cache {
+    /// Forgets how often the cache was cleared, before a new search.
+    pub(crate) fn reset(&mut self) {
+        self.clears = 0;
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.transitions.clear();
+        self.clears += 1;
+    }
+}
+
+// Like the DFA, the chars are split into intervals at `boundaries`, but a state, which is
+// a set of NFA states, is only made when a search first reaches it. At most `capacity`
+// states are cached, and the cache is cleared when it is full.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Automaton {
+    nfa: NFA,
+    kind: MatchKind,
+    start: Vec<usize>,
+    boundaries: Vec<char>,
+    looks: Vec<Look>,
+    transitions: Transitions,
+    capacity: usize,
+}
+
+impl Automaton {
+    pub(crate) fn new(nfa: NFA, kind: MatchKind, capacity: usize) -> Self {
+        let (boundaries, looks) = calc_alphabet(&nfa);
+        let transitions = calc_transitions(&nfa, &boundaries);
+        let start = kind.arrange(&nfa, nfa.calc_epsilon_closure(nfa.start));
+
+        Self {
+            nfa,
+            kind,
+            start,
+            boundaries,
+            looks,
+            transitions,
+            capacity,
+        }
+    }
+
+    /// Returns the end of the longest accepted prefix of `text[start..]`, seeing the
+    /// char before `start` as assertions do.
+    pub(crate) fn longest_prefix(
+        &self,
+        cache: &mut Cache,
+        text: &str,
+        start: usize,
+    ) -> Option<usize> {
+        let chars = text[start..].char_indices().map(|(i, c)| (start + i, c));
+
+        self.scan(
+            cache,
+            text[..start].chars().next_back(),
+            chars,
+            text.len(),
+            None,
+        )
+    }
+
+    /// Returns the start of the longest suffix of `text[min..end]` that an automaton of a
+    /// reversed NFA accepts, reading the text backwards from `end` and seeing the chars
+    /// around it as assertions do.
+    pub(crate) fn longest_suffix(
+        &self,
+        cache: &mut Cache,
+        text: &str,
+        min: usize,
+        end: usize,
+    ) -> Option<usize> {
+        let chars = text[min..end]
+            .char_indices()
+            .rev()
+            .map(|(i, c)| (min + i + c.len_utf8(), c));
+
+        self.scan(
+            cache,
+            text[end..].chars().next(),
+            chars,
+            min,
+            text[..min].chars().next_back(),
+        )
+    }
+
+    // Runs the automaton over `chars`, each paired with the position before it in the order
+    // they are read, starting after the char `behind`. Returns the last position where it
+    // accepted, which is `last`, followed by the char `after`, once every char is read.
+    fn scan(
+        &self,
+        cache: &mut Cache,
+        behind: Option<char>,
+        mut chars: impl Iterator<Item = (usize, char)>,
+        last: usize,
+        after: Option<char>,
+    ) -> Option<usize> {
+        let behind = self.behind(behind);
+
+        if cache.clears > MAX_CLEARS {
+            return self.simulate(self.start.clone(), behind, chars, last, after);
+        }
+
+        let mut current = self.add_state(cache, self.start.clone(), behind);
+        let mut end = None;
+
+        while let Some((position, c)) = chars.next() {
+            crate::count_step();
+
+            if cache.states[current].accepting.contains(&Look::of(Some(c))) {
+                end = Some(position);
+            }
+
+            if cache.clears > MAX_CLEARS {
+                let state = &cache.states[current];
+                let destinations = state.destinations.clone();
+                let chars = [(position, c)].into_iter().chain(chars);
+
+                return self
+                    .simulate(destinations, state.behind, chars, last, after)
+                    .or(end);
+            }
+
+            match self.next(cache, current, c) {
+                Some(destination) => current = destination,
+                None => return end,
+            }
+        }
+
+        if cache.states[current].accepting.contains(&Look::of(after)) {
+            end = Some(last);
+        }
+
+        end
+    }
+
+    // Runs the NFA on the sets of its states directly, without caching them.
+    fn simulate(
+        &self,
+        mut destinations: Vec<usize>,
+        mut behind: Look,
+        chars: impl Iterator<Item = (usize, char)>,
+        last: usize,
+        after: Option<char>,
+    ) -> Option<usize> {
+        let mut end = None;
+
+        for (position, c) in chars {
+            crate::count_step();
+
+            if self.is_accepting(&destinations, behind, Look::of(Some(c))) {
+                end = Some(position);
+            }
+
+            match self.step(&destinations, behind, self.interval(c)) {
+                Some(next) => (destinations, behind) = next,
+                None => return end,
+            }
+        }
+
+        if self.is_accepting(&destinations, behind, Look::of(after)) {
+            end = Some(last);
+        }
+
+        end
+    }
+
+    fn next(&self, cache: &mut Cache, from: usize, c: char) -> Option<usize> {
+        let interval = self.interval(c);
+
+        if let Some(destination) = cache.transitions.get(&(from, interval)) {
+            return *destination;
+        }
+
+        let state = &cache.states[from];
+        let Some((destinations, behind)) = self.step(&state.destinations, state.behind, interval)
+        else {
+            cache.transitions.insert((from, interval), None);
+            return None;
+        };
+
+        let clears = cache.clears;
+        let destination = self.add_state(cache, destinations, behind);
+
+        // A cleared cache no longer holds `from`.
+        if clears == cache.clears {
+            cache
+                .transitions
+                .insert((from, interval), Some(destination));
+        }
+
+        Some(destination)
+    }
+
+    fn add_state(&self, cache: &mut Cache, destinations: Vec<usize>, behind: Look) -> usize {
+        let key = (destinations, behind);
+
+        if let Some(id) = cache.ids.get(&key) {
+            return *id;
+        }
+
+        if cache.states.len() >= self.capacity {
+            cache.clear();
+        }
+
+        let (destinations, behind) = key;
+        let accepting = Look::ALL
+            .into_iter()
+            .filter(|ahead| self.is_accepting(&destinations, behind, *ahead))
+            .collect();
+        let id = cache.states.len();
+
+        cache.ids.insert((destinations.clone(), behind), id);
+        cache.states.push(State {
+            destinations,
+            behind,
+            accepting,
+        });
+
+        id
+    }
+
+    fn is_accepting(&self, destinations: &[usize], behind: Look, ahead: Look) -> bool {
+        resolve(&self.nfa, self.kind, destinations, behind, ahead)
+            .iter()
+            .any(|destination| self.nfa.accepts.contains(destination))
+    }
+
+    // Returns the NFA states reached from `destinations` by a char in the interval, in the
+    // same order as the DFA would list them.
+    fn step(
+        &self,
+        destinations: &[usize],
+        behind: Look,
+        interval: usize,
+    ) -> Option<(Vec<usize>, Look)> {
+        let c = self.boundaries[interval];
+        let ahead = self.looks[interval];
+        let mut next_destinations = Vec::new();
+
+        for destination in resolve(&self.nfa, self.kind, destinations, behind, ahead) {
+            for next in self.calc_destinations(destination, interval) {
+                extend_unique(&mut next_destinations, self.nfa.calc_epsilon_closure(*next));
+            }
+        }
+
+        match next_destinations.is_empty() {
+            true => None,
+            false => Some((
+                self.kind.arrange(&self.nfa, next_destinations),
+                self.behind(Some(c)),
+            )),
+        }
+    }
+
+    // Returns the NFA states that `from` leads to by a char in the interval.
+    fn calc_destinations(&self, from: usize, interval: usize) -> &[usize] {
+        let Some(runs) = self.transitions.get(&from) else {
+            return &[];
+        };
+        let index = runs.partition_point(|(run, _)| run.end <= interval);
+
+        match runs.get(index) {
+            Some((run, destinations)) if run.contains(&interval) => destinations,
+            _ => &[],
+        }
+    }
+
+    fn behind(&self, c: Option<char>) -> Look {
+        match self.nfa.assertions.is_empty() {
+            true => Look::Boundary,
+            false => Look::of(c),
+        }
+    }
+
+    fn interval(&self, c: char) -> usize {
+        self.boundaries.partition_point(|boundary| *boundary <= c) - 1
+    }
+}
+
+// Returns the char transitions of each NFA state as disjoint runs of intervals, split where
+// the states they lead to change. The states of a run that several ranges cover are listed
+// in the order of the ranges.
+fn calc_transitions(nfa: &NFA, boundaries: &[char]) -> Transitions {
+    let mut transitions = HashMap::new();
+
+    for (from, nfa_transitions) in &nfa.transitions {
+        let mut spans = nfa_transitions
+            .iter()
+            .filter_map(|(range, destinations)| Some((*range.as_ref()?, destinations)))
+            .collect::<Vec<_>>();
+        spans.sort();
+
+        let spans = spans
+            .into_iter()
+            .map(|(range, destinations)| (calc_intervals(boundaries, range), destinations))
+            .collect::<Vec<_>>();
+        let mut cuts = Vec::from_iter(spans.iter().flat_map(|(span, _)| [span.start, span.end]));
+        cuts.sort();
+        cuts.dedup();
+
+        let mut runs: Vec<(Range<usize>, Vec<usize>)> = Vec::new();
+        // The spans that cover the current run, which are sorted as the ranges are.
+        let mut covering: Vec<usize> = Vec::new();
+        let mut next_span = 0;
+
+        for cut in cuts.windows(2) {
+            let (start, end) = (cut[0], cut[1]);
+
+            covering.retain(|span| spans[*span].0.end > start);
+
+            while next_span < spans.len() && spans[next_span].0.start <= start {
+                covering.push(next_span);
+                next_span += 1;
+            }
+
+            let mut destinations = Vec::new();
+
+            for span in &covering {
+                extend_unique(&mut destinations, spans[*span].1.iter().cloned());
+            }
+
+            match runs.last_mut() {
+                _ if destinations.is_empty() => {}
+                // A run that leads to the same states as the one before it extends it.
+                Some((run, last)) if run.end == start && *last == destinations => run.end = end,
+                _ => runs.push((start..end, destinations)),
+            }
+        }
+
+        if !runs.is_empty() {
+            transitions.insert(*from, runs);
+        }
+    }
+
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        deterministic_finite_automaton::MatchKind,
+        lazy_deterministic_finite_automaton::{Automaton, Cache, MAX_CLEARS},
+        nondeterministic_finite_automaton::Automaton as NFA,
+    };
+    use parser::parse;
+
+    #[test]
+    fn test_longest_prefix() {
+        let nfa = NFA::from(parse("(a|b)*abb").unwrap());
+        let automaton = Automaton::new(nfa, MatchKind::LeftmostFirst, 100);
+        let mut cache = Cache::default();
+
+        assert_eq!(automaton.longest_prefix(&mut cache, "xabbabb", 0), None);
+        assert_eq!(automaton.longest_prefix(&mut cache, "xabbabb", 1), Some(7));
+        assert_eq!(cache.clears, 0);
+        assert!(cache.states.len() <= 100);
+    }
+
+    #[test]
+    fn test_thrashing() {
+        let nfa = NFA::from(parse("(a|b)*a(a|b){3}").unwrap());
+        let automaton = Automaton::new(nfa, MatchKind::LeftmostFirst, 2);
+        let mut cache = Cache::default();
+
+        assert_eq!(
+            automaton.longest_prefix(&mut cache, &"aabb".repeat(10), 0),
+            Some(40)
+        );
+        assert!(cache.clears > MAX_CLEARS);
+        assert!(cache.states.len() <= 2);
+
+        cache.reset();
+
+        assert_eq!(automaton.longest_prefix(&mut cache, "bbbb", 0), None);
+    }
+}
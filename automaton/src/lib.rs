@@ -1,46 +1,491 @@
 use anyhow::Result;
-use deterministic_finite_automaton::Automaton as DFA;
-use nondeterministic_finite_automaton::Automaton as NFA;
-use parser::parse;
+use deterministic_finite_automaton::{Look, MatchKind};
+use lazy_deterministic_finite_automaton::{Automaton as LazyDFA, Cache};
+use nondeterministic_finite_automaton::count_states;
+use parser::{parse_with, Config, Node};
+use std::{fmt, sync::Mutex};
 
+pub use deterministic_finite_automaton::Automaton as DFA;
+pub use nondeterministic_finite_automaton::Automaton as NFA;
+pub use parser::{Captures, Match, ParseError, ParseErrorKind, RegexExt, Replacer, Split, SplitN};
+
+pub mod bytes;
 pub(crate) mod deterministic_finite_automaton;
+pub(crate) mod format;
+pub(crate) mod lazy_deterministic_finite_automaton;
 pub(crate) mod nondeterministic_finite_automaton;
+pub(crate) mod utf8;
+
+/// An error for a pattern whose automata would exceed a limit set on the `RegexBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    NfaStates { limit: usize },
+    DfaStates { limit: usize },
+    DfaWork { limit: usize },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NfaStates { limit } => write!(f, "the NFA would have more than {limit} states"),
+            Self::DfaStates { limit } => write!(f, "the DFA would have more than {limit} states"),
+            Self::DfaWork { limit } => {
+                write!(f, "building the DFA would take more than {limit} steps")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// Counts the steps taken to build a DFA, failing once there are more than `limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Budget {
+    limit: usize,
+    spent: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self { limit, spent: 0 }
+    }
+
+    pub(crate) fn spend(&mut self, steps: usize) -> Result<(), LimitError> {
+        self.spent = self.spent.saturating_add(steps);
+
+        match self.spent > self.limit {
+            true => Err(LimitError::DfaWork { limit: self.limit }),
+            false => Ok(()),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Regex {
-    automaton: DFA,
+pub struct RegexBuilder {
+    pattern: String,
+    config: Config,
+    nfa_state_limit: usize,
+    dfa_state_limit: usize,
+    dfa_work_limit: usize,
+    cache_capacity: usize,
+    allow_invalid_utf8: bool,
 }
 
-impl Regex {
-    pub fn new(pattern: &str) -> Result<Self> {
-        let node = parse(pattern)?;
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            config: Config::default(),
+            nfa_state_limit: 100_000,
+            dfa_state_limit: 10_000,
+            dfa_work_limit: 10_000_000,
+            cache_capacity: 1_000,
+            allow_invalid_utf8: false,
+        }
+    }
+
+    /// Sets the deepest nesting of groups allowed.
+    pub fn nest_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.nest_limit = limit;
+        self
+    }
+
+    /// Sets the largest number of NFA states the pattern may compile to.
+    pub fn nfa_state_limit(&mut self, limit: usize) -> &mut Self {
+        self.nfa_state_limit = limit;
+        self
+    }
+
+    /// Sets the largest number of states each DFA may have before it is minimized.
+    pub fn dfa_state_limit(&mut self, limit: usize) -> &mut Self {
+        self.dfa_state_limit = limit;
+        self
+    }
+
+    /// Sets the largest number of steps building the DFAs of a regex may take, where a step
+    /// is the visit of an NFA state, so that a pattern with few states but large classes or
+    /// long chains of optional parts cannot hold the CPU for long.
+    pub fn dfa_work_limit(&mut self, limit: usize) -> &mut Self {
+        self.dfa_work_limit = limit;
+        self
+    }
 
-        let mut nfa = NFA::from(node);
-        nfa.remove_epsilon_transitions();
+    /// Sets the number of states a `LazyRegex` caches before it clears its cache.
+    pub fn cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Sets the largest count allowed in a bounded repetition such as `a{n,m}`.
+    pub fn repeat_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.repeat_limit = limit;
+        self
+    }
+
+    /// Lets `.` match `\n`.
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.dot_matches_newline = yes;
+        self
+    }
+
+    /// Lets `^` and `$` match at the start and end of each line.
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.multi_line = yes;
+        self
+    }
+
+    /// Matches letters regardless of their case, as `(?i)` does.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.case_insensitive = yes;
+        self
+    }
+
+    /// Makes `\d`, `\s`, `\w` and `\b` Unicode-aware. Enabled by default.
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.unicode = yes;
+        self
+    }
+
+    /// Lets `.` and the other classes that match every non-ASCII char, such as `[^a]`, also
+    /// match any single byte from 0x80 up, so that a `bytes::Regex` can match text that is
+    /// not valid UTF-8. It has no effect on the regexes that match `&str`.
+    pub fn allow_invalid_utf8(&mut self, yes: bool) -> &mut Self {
+        self.allow_invalid_utf8 = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex> {
+        self.build_from(parse_with(&self.pattern, &self.config)?)
+    }
+
+    /// Builds the regex from the node of the pattern, for a caller that has already parsed
+    /// it. The options of the parser set on the builder are then not used.
+    pub fn build_from(&self, node: Node) -> Result<Regex> {
+        let mut nfa = self.build_nfa_from(node)?;
+        let mut budget = Budget::new(self.dfa_work_limit);
+        let (forward, reverse) = self.build_search_dfas(&nfa, &mut budget)?;
 
-        let mut dfa = DFA::from(nfa);
+        let mut dfa = self.build_dfa_without_epsilons(&mut nfa, &mut budget)?;
         dfa.minimize();
 
-        Ok(Self { automaton: dfa })
+        Ok(Regex {
+            automaton: dfa,
+            forward,
+            reverse,
+        })
+    }
+
+    /// Builds a `LazyRegex`, which makes DFA states only as searches reach them. The DFA
+    /// state limit does not apply, as the cache capacity bounds the states kept.
+    pub fn build_lazy(&self) -> Result<LazyRegex> {
+        self.build_lazy_from(parse_with(&self.pattern, &self.config)?)
+    }
+
+    /// Builds the `LazyRegex` from the node of the pattern, as `build_from` does.
+    pub fn build_lazy_from(&self, node: Node) -> Result<LazyRegex> {
+        let nfa = self.build_nfa_from(node)?;
+        let forward = nfa.unanchored();
+        let reverse = nfa.reverse();
+
+        Ok(LazyRegex {
+            automaton: LazyDFA::new(nfa, MatchKind::All, self.cache_capacity),
+            forward: LazyDFA::new(forward, MatchKind::LeftmostFirst, self.cache_capacity),
+            reverse: LazyDFA::new(reverse, MatchKind::All, self.cache_capacity),
+            caches: Mutex::default(),
+        })
+    }
+
+    /// Builds the NFA of the pattern, which has epsilon transitions.
+    pub fn build_nfa(&self) -> Result<NFA> {
+        self.build_nfa_from(parse_with(&self.pattern, &self.config)?)
+    }
+
+    fn build_nfa_from(&self, node: Node) -> Result<NFA> {
+        if count_states(&node) > self.nfa_state_limit {
+            let limit = self.nfa_state_limit;
+            return Err(LimitError::NfaStates { limit }.into());
+        }
+
+        Ok(NFA::from(node))
+    }
+
+    /// Builds the DFA that `Regex::matches` runs, before it is minimized.
+    pub fn build_dfa(&self) -> Result<DFA> {
+        let mut nfa = self.build_nfa()?;
+        let mut budget = Budget::new(self.dfa_work_limit);
+
+        Ok(self.build_dfa_without_epsilons(&mut nfa, &mut budget)?)
+    }
+
+    // Builds the DFAs that find the end of the leftmost-first match in a pass forwards from
+    // the start of the search, and then its start in a pass backwards from the end.
+    fn build_search_dfas(&self, nfa: &NFA, budget: &mut Budget) -> Result<(DFA, DFA), LimitError> {
+        let limit = self.dfa_state_limit;
+
+        let mut forward = DFA::new(&nfa.unanchored(), MatchKind::LeftmostFirst, limit, budget)?;
+        forward.minimize();
+
+        let mut reverse = DFA::new(&nfa.reverse(), MatchKind::All, limit, budget)?;
+        reverse.minimize();
+
+        Ok((forward, reverse))
+    }
+
+    // Removing the epsilon transitions counts towards the work of building the DFA.
+    fn build_dfa_without_epsilons(
+        &self,
+        nfa: &mut NFA,
+        budget: &mut Budget,
+    ) -> Result<DFA, LimitError> {
+        nfa.remove_epsilon_transitions_within(budget)?;
+
+        DFA::new(nfa, MatchKind::All, self.dfa_state_limit, budget)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    automaton: DFA,
+    forward: DFA,
+    reverse: DFA,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        RegexBuilder::new(pattern).build()
     }
 
     pub fn matches(&self, text: &str) -> bool {
-        let mut current = self.automaton.start;
+        let mut current = self.automaton.start(Look::Boundary);
 
         for c in text.chars() {
-            match self.automaton.transitions.get(&(current, c)) {
-                Some(destination) => current = *destination,
+            match self.automaton.next(current, c) {
+                Some(destination) => current = destination,
                 None => return false,
             }
         }
 
-        self.automaton.accepts.contains(&current)
+        self.automaton.is_accepting(current, Look::Boundary)
+    }
+
+    /// Returns true if the regex matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the leftmost-first match in the text.
+    pub fn find(&self, text: &str) -> Option<Match> {
+        self.find_at(text, 0)
+    }
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> FindIter<'r, 't> {
+        FindIter {
+            regex: Searcher::Eager(self),
+            text,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    fn find_at(&self, text: &str, start: usize) -> Option<Match> {
+        find_from(
+            || self.forward.longest_prefix(text, start),
+            |end| self.reverse.longest_suffix(text, start, end),
+        )
+    }
+}
+
+impl parser::Regex for Regex {
+    fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.matches(text)
+    }
+
+    fn find(&self, text: &str) -> Option<Match> {
+        self.find(text)
+    }
+
+    fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a> {
+        Box::new(self.find_iter(text))
+    }
+}
+
+/// A regex matched by DFAs whose states are made while searching, rather than all up
+/// front, and kept in a bounded cache. Building one takes time linear in the pattern even
+/// where the full DFA would be exponential, as for `(a|b)*a(a|b){20}`.
+///
+/// The cache is shared by the searches, which take turns using it.
+#[derive(Debug)]
+pub struct LazyRegex {
+    automaton: LazyDFA,
+    forward: LazyDFA,
+    reverse: LazyDFA,
+    caches: Mutex<(Cache, Cache, Cache)>,
+}
+
+impl LazyRegex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        RegexBuilder::new(pattern).build_lazy()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let mut caches = self.caches.lock().unwrap();
+        caches.0.reset();
+
+        self.automaton.longest_prefix(&mut caches.0, text, 0) == Some(text.len())
+    }
+
+    /// Returns true if the regex matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the leftmost-first match in the text.
+    pub fn find(&self, text: &str) -> Option<Match> {
+        self.find_at(text, 0)
+    }
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> FindIter<'r, 't> {
+        FindIter {
+            regex: Searcher::Lazy(self),
+            text,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    fn find_at(&self, text: &str, start: usize) -> Option<Match> {
+        let mut caches = self.caches.lock().unwrap();
+        let (_, forward, reverse) = &mut *caches;
+        forward.reset();
+        reverse.reset();
+
+        find_from(
+            || self.forward.longest_prefix(forward, text, start),
+            |end| self.reverse.longest_suffix(reverse, text, start, end),
+        )
+    }
+}
+
+impl parser::Regex for LazyRegex {
+    fn new(pattern: &str) -> Result<Self> {
+        LazyRegex::new(pattern)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.matches(text)
+    }
+
+    fn find(&self, text: &str) -> Option<Match> {
+        self.find(text)
+    }
+
+    fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a> {
+        Box::new(self.find_iter(text))
+    }
+}
+
+// Returns the leftmost-first match, given a forward pass that finds its end and a backward
+// pass from that end that finds the leftmost start of a match ending there, which is its
+// start, as no match starts before it.
+pub(crate) fn find_from(
+    forward: impl FnOnce() -> Option<usize>,
+    reverse: impl FnOnce(usize) -> Option<usize>,
+) -> Option<Match> {
+    let end = forward()?;
+    let start = reverse(end).expect("a match ends at `end`");
+
+    Some(Match::new(start, end))
+}
+
+#[cfg(test)]
+thread_local! {
+    // The chars that the DFAs have read on this thread.
+    static STEPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// Counts a char read by a DFA, so that the tests can check how much of a text a search
+// reads.
+pub(crate) fn count_step() {
+    #[cfg(test)]
+    STEPS.with(|steps| steps.set(steps.get() + 1));
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Searcher<'r> {
+    Eager(&'r Regex),
+    Lazy(&'r LazyRegex),
+}
+
+impl Searcher<'_> {
+    fn find_at(&self, text: &str, start: usize) -> Option<Match> {
+        match self {
+            Self::Eager(regex) => regex.find_at(text, start),
+            Self::Lazy(regex) => regex.find_at(text, start),
+        }
+    }
+}
+
+/// An iterator over all non-overlapping matches in a text.
+///
+/// An empty match immediately following another match is skipped, and the
+/// search always advances by at least one char after an empty match.
+#[derive(Debug, Clone)]
+pub struct FindIter<'r, 't> {
+    regex: Searcher<'r>,
+    text: &'t str,
+    last_end: usize,
+    last_match: Option<usize>,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.last_end > self.text.len() {
+                return None;
+            }
+
+            let m = self.regex.find_at(self.text, self.last_end)?;
+
+            if m.is_empty() {
+                self.last_end = match self.text[m.end()..].chars().next() {
+                    Some(c) => m.end() + c.len_utf8(),
+                    None => m.end() + 1,
+                };
+
+                if Some(m.end()) == self.last_match {
+                    continue;
+                }
+            } else {
+                self.last_end = m.end();
+            }
+
+            self.last_match = Some(m.end());
+            return Some(m);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Regex;
+    use crate::{
+        Captures, LazyRegex, LimitError, ParseError, ParseErrorKind, Regex, RegexBuilder, RegexExt,
+    };
+    use std::borrow::Cow;
+
+    // Returns the chars that the DFAs read in running `search`.
+    fn steps(search: impl FnOnce()) -> usize {
+        let before = super::STEPS.with(|steps| steps.get());
+        search();
+        super::STEPS.with(|steps| steps.get()) - before
+    }
 
     #[test]
     fn test_matches() {
@@ -53,5 +498,419 @@ mod tests {
         assert!(!regex.matches("aa"));
         assert!(!regex.matches("babab"));
         assert!(!regex.matches("abbabba"));
+
+        let regex = Regex::new("[a-c][^a-c]*[ac]").unwrap();
+
+        assert!(regex.matches("ac"));
+        assert!(regex.matches("bxyzc"));
+        assert!(regex.matches("cñ\u{10FFFF}a"));
+
+        assert!(!regex.matches("abc"));
+        assert!(!regex.matches("dxa"));
+
+        let regex = Regex::new("(a*b*)*").unwrap();
+
+        assert!(regex.matches(""));
+        assert!(regex.matches("abba"));
+
+        assert!(!regex.matches("abc"));
+    }
+
+    #[test]
+    fn test_find() {
+        let regex = Regex::new("ab*").unwrap();
+
+        assert_eq!(regex.find("xxabbby").map(|m| m.range()), Some(2..6));
+        assert_eq!(regex.find("xxa").map(|m| m.range()), Some(2..3));
+        assert_eq!(regex.find("xxx"), None);
+
+        let regex = Regex::new("a|ab").unwrap();
+
+        assert_eq!(regex.find("cab").map(|m| m.range()), Some(1..2));
+
+        let regex = Regex::new("ab|a").unwrap();
+
+        assert_eq!(regex.find("cab").map(|m| m.range()), Some(1..3));
+
+        let regex = Regex::new("b*").unwrap();
+
+        assert_eq!(regex.find("abb").map(|m| m.range()), Some(0..0));
+
+        let regex = Regex::new("[0-9][0-9]*").unwrap();
+
+        assert_eq!(regex.find("abc 0123 45").map(|m| m.range()), Some(4..8));
+
+        let regex = Regex::new("é(a|ü)").unwrap();
+
+        assert_eq!(regex.find("xéü").map(|m| m.range()), Some(1..5));
+
+        let regex = Regex::new(r"\ba+b").unwrap();
+
+        assert_eq!(regex.find("caab aaab").map(|m| m.range()), Some(5..9));
+
+        // A text without a match is scanned once, rather than once from each position.
+        let text = "x".repeat(100_000);
+        let (regex, lazy, bytes) = (
+            Regex::new("x*y").unwrap(),
+            LazyRegex::new("x*y").unwrap(),
+            crate::bytes::Regex::new("x*y").unwrap(),
+        );
+
+        assert_eq!(steps(|| assert_eq!(regex.find(&text), None)), text.len());
+        assert_eq!(steps(|| assert_eq!(lazy.find(&text), None)), text.len());
+        assert_eq!(
+            steps(|| assert_eq!(bytes.find(text.as_bytes()), None)),
+            text.len()
+        );
+    }
+
+    #[test]
+    fn test_is_match() {
+        let regex = Regex::new("(a|b)*a(a|b)(a|b)").unwrap();
+
+        assert!(regex.is_match("xxaaayy"));
+        assert!(regex.is_match("ccbabbcc"));
+
+        assert!(!regex.is_match("xxaayy"));
+        assert!(!regex.is_match(""));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let ranges = |pattern: &str, text: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges("a*", "baaab"), vec![0..0, 1..4, 5..5]);
+        assert_eq!(ranges("ab|b", "abbab"), vec![0..2, 2..3, 3..5]);
+        assert_eq!(ranges("", "aé"), vec![0..0, 1..1, 3..3]);
+        assert_eq!(ranges("a|", "bab"), vec![0..0, 1..2, 3..3]);
+        assert!(ranges("x", "abc").is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new("a+").unwrap();
+        let lazy = LazyRegex::new("a+").unwrap();
+
+        assert_eq!(regex.replace("baab a", "<$0>"), "b<aa>b a");
+        assert_eq!(lazy.replace_all("baab a", "<$0>"), "b<aa>b <a>");
+        assert_eq!(regex.replacen("a a a", 2, "${1}x"), "x x a");
+        assert_eq!(
+            lazy.replace_all("baab", |captures: &Captures| captures.len().to_string()),
+            "b1b",
+        );
+        assert!(matches!(regex.replace_all("bc", "x"), Cow::Borrowed("bc")));
+    }
+
+    #[test]
+    fn test_repeat() {
+        let ranges = |pattern: &str, text: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges("a+", "baaab"), vec![1..4]);
+        assert_eq!(ranges("a+?", "baaab"), vec![1..2, 2..3, 3..4]);
+        assert_eq!(ranges("ab?", "abaab"), vec![0..2, 2..3, 3..5]);
+        assert_eq!(ranges("ab??", "abaab"), vec![0..1, 2..3, 3..4]);
+        assert_eq!(ranges("a{2}", "aaaaa"), vec![0..2, 2..4]);
+        assert_eq!(ranges("a{2,}", "aaaaab"), vec![0..5]);
+        assert_eq!(ranges("a{2,3}", "aaaaa"), vec![0..3, 3..5]);
+        assert_eq!(ranges("a{2,3}?", "aaaaa"), vec![0..2, 2..4]);
+        assert_eq!(ranges("<[^>]*?>x", "<a><b>x"), vec![3..7]);
+        assert_eq!(ranges("(?:b|a??)+", "abab"), vec![0..0, 1..4]);
+        assert_eq!(ranges("(?:b|a??){2,}", "abab"), vec![0..0, 1..2, 3..4]);
+
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(RegexBuilder::new("a{1001}")
+            .repeat_limit(2000)
+            .build()
+            .unwrap()
+            .matches(&"a".repeat(1001)));
+    }
+
+    #[test]
+    fn test_any() {
+        let regex = Regex::new("a.c").unwrap();
+
+        assert!(regex.matches("abc"));
+        assert!(regex.matches("a.c"));
+        assert!(regex.matches("aéc"));
+        assert!(regex.matches("a\u{10FFFF}c"));
+
+        assert!(!regex.matches("a\nc"));
+        assert!(!regex.matches("ac"));
+
+        assert_eq!(
+            regex
+                .find_iter("a\nc abc")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![4..7],
+        );
+
+        let regex = RegexBuilder::new("a.c")
+            .dot_matches_newline(true)
+            .build()
+            .unwrap();
+
+        assert!(regex.matches("a\nc"));
+
+        let regex = Regex::new("a.*c").unwrap();
+
+        assert_eq!(regex.find("xabcbc\nc").map(|m| m.range()), Some(1..6));
+    }
+
+    #[test]
+    fn test_assertion() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let regex = |pattern| Regex::new(pattern).unwrap();
+        let multi_line = |pattern| RegexBuilder::new(pattern).multi_line(true).build().unwrap();
+
+        assert!(regex("^abc$").matches("abc"));
+        assert!(regex("^$").matches(""));
+        assert!(!regex("a^b").is_match("ab"));
+        assert!(regex(r"a|\B").matches(""));
+        assert!(regex("^a|b").matches("b"));
+        assert!(!regex("x|^a").matches("ba"));
+
+        assert_eq!(ranges(&regex("^a"), "aa\na"), vec![0..1]);
+        assert_eq!(ranges(&regex("a$"), "a\naa"), vec![3..4]);
+        assert_eq!(ranges(&regex(r"\Aa|b\z"), "abab"), vec![0..1, 3..4]);
+        assert_eq!(
+            ranges(&regex(r"\bfoo\b"), "foo foobar barfoo foo"),
+            vec![0..3, 18..21],
+        );
+        assert_eq!(ranges(&regex(r"\Bo\B"), "foo"), vec![1..2]);
+        assert_eq!(ranges(&regex(r"\bb"), "ab b"), vec![3..4]);
+        assert_eq!(ranges(&regex(r"\b"), "ab cd"), vec![0..0, 2..2, 3..3, 5..5]);
+
+        assert_eq!(ranges(&multi_line("^a"), "aa\na"), vec![0..1, 3..4]);
+        assert_eq!(ranges(&multi_line("a$"), "a\naa"), vec![0..1, 3..4]);
+        assert_eq!(ranges(&multi_line("^$"), "a\n\nb"), vec![2..2]);
+        assert_eq!(ranges(&multi_line(r"\Aa"), "a\na"), vec![0..1]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = Regex::new("a(b").unwrap_err();
+        let error = error.downcast_ref::<ParseError>().unwrap();
+
+        assert_eq!(error.kind(), ParseErrorKind::UnclosedGroup);
+        assert_eq!(error.offset(), 1);
+    }
+
+    #[test]
+    fn test_perl_class() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let unicode = |pattern| Regex::new(pattern).unwrap();
+        let ascii = |pattern| RegexBuilder::new(pattern).unicode(false).build().unwrap();
+
+        assert_eq!(ranges(&unicode(r"\d+"), "a12 \u{663}4"), vec![1..3, 4..7]);
+        assert_eq!(ranges(&ascii(r"\d+"), "a12 \u{663}4"), vec![1..3, 6..7]);
+        assert_eq!(ranges(&unicode(r"\w+"), "héllo wörld"), vec![0..6, 7..13]);
+        assert_eq!(
+            ranges(&ascii(r"\w+"), "héllo wörld"),
+            vec![0..1, 3..6, 7..8, 10..13],
+        );
+        assert_eq!(ranges(&unicode(r"\W"), "a-b"), vec![1..2]);
+        assert_eq!(ranges(&unicode(r"[\d\s]+"), "1 2x"), vec![0..3]);
+        assert_eq!(ranges(&unicode("[^\\S\n]+"), "a \t\nb"), vec![1..3]);
+        assert!(unicode(r"a\sb").is_match("a\u{3000}b"));
+        assert!(!ascii(r"a\sb").is_match("a\u{3000}b"));
+        assert!(!unicode(r"a\Db").is_match("a1b"));
+        assert_eq!(ranges(&unicode(r"\b"), "é"), vec![0..0, 2..2]);
+        assert!(ranges(&ascii(r"\b"), "é").is_empty());
+        assert_eq!(ranges(&ascii(r"\B.\b"), "éa"), vec![0..2]);
+    }
+
+    #[test]
+    fn test_property_class() {
+        let ranges = |pattern, text| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges(r"\p{Greek}+", "abc αβγ def"), vec![4..10]);
+        assert_eq!(ranges(r"\p{Han}", "漢字abc"), vec![0..3, 3..6]);
+        assert_eq!(ranges(r"\P{L}+", "ab12cd"), vec![2..4]);
+        assert_eq!(ranges(r"[\p{Lu}\d]+", "aB1c"), vec![1..3]);
+        assert_eq!(ranges(r"\p{Nd}", "x\u{663}"), vec![1..3]);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let regex = |pattern| Regex::new(pattern).unwrap();
+        let case_insensitive = |pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            ranges(&case_insensitive("k"), "kK\u{212A}x"),
+            vec![0..1, 1..2, 2..5]
+        );
+        assert_eq!(ranges(&case_insensitive("[a-c]+"), "xAbCd"), vec![1..4]);
+        assert_eq!(ranges(&case_insensitive("[^k]"), "K\u{212A}kx"), vec![5..6]);
+        assert_eq!(ranges(&regex("(?i)σ"), "Σσς"), vec![0..2, 2..4, 4..6]);
+        assert_eq!(ranges(&regex("a(?i)b|c"), "aB AB C"), vec![0..2, 6..7]);
+        assert_eq!(ranges(&regex("(?i:a)b"), "Ab AB"), vec![0..2]);
+        assert_eq!(ranges(&case_insensitive("a(?-i)b"), "AB Ab"), vec![3..5]);
+        assert!(regex(r"(?i)\p{Lu}").is_match("a"));
+    }
+
+    #[test]
+    fn test_limit() {
+        let limit_error = |builder: &RegexBuilder| {
+            let error = builder.build().unwrap_err();
+            *error.downcast_ref::<LimitError>().unwrap()
+        };
+
+        assert_eq!(
+            limit_error(&RegexBuilder::new("(a{1000}){1000}")),
+            LimitError::NfaStates { limit: 100_000 },
+        );
+        assert_eq!(
+            limit_error(RegexBuilder::new("a{50}").nfa_state_limit(99)),
+            LimitError::NfaStates { limit: 99 },
+        );
+        assert!(RegexBuilder::new("a{50}")
+            .nfa_state_limit(100)
+            .build()
+            .is_ok());
+        assert_eq!(
+            limit_error(RegexBuilder::new("(a|b)*a(a|b){8}").dfa_state_limit(100)),
+            LimitError::DfaStates { limit: 100 },
+        );
+        assert_eq!(
+            limit_error(RegexBuilder::new("(?:x?){1000}x{1000}").dfa_work_limit(100_000)),
+            LimitError::DfaWork { limit: 100_000 },
+        );
+        assert_eq!(
+            limit_error(RegexBuilder::new("(a|b)*a(a|b){8}").dfa_work_limit(1_000)),
+            LimitError::DfaWork { limit: 1_000 },
+        );
+        assert!(LazyRegex::new("(?:x?){1000}x{1000}").is_ok());
+
+        let error =
+            Regex::new(&format!("{}a{}", "(".repeat(10_000), ")".repeat(10_000))).unwrap_err();
+        let error = error.downcast_ref::<ParseError>().unwrap();
+
+        assert_eq!(error.kind(), ParseErrorKind::NestingTooDeep { limit: 100 });
+        assert!(RegexBuilder::new("((a))").nest_limit(1).build().is_err());
+
+        assert!(Regex::new("((((a*)*)*)*)*").unwrap().matches("aaa"));
+        assert!(Regex::new(&"a".repeat(2_000))
+            .unwrap()
+            .is_match(&"a".repeat(2_000)));
+    }
+
+    #[test]
+    fn test_lazy() {
+        let patterns = [
+            "a|ab",
+            "(a|b)*abb",
+            "a*?b|c+",
+            r"\bfoo\b|^x$",
+            r"(?m)^\w+$",
+            r"[\p{Greek}\d]{2,}",
+            "",
+        ];
+        let texts = [
+            "ab",
+            "abababb",
+            "caab",
+            "foo foobar",
+            "x\nfoo\ny",
+            "αβ12 a1",
+            "",
+        ];
+
+        for pattern in patterns {
+            let eager = Regex::new(pattern).unwrap();
+            let lazy = LazyRegex::new(pattern).unwrap();
+            let thrashing = RegexBuilder::new(pattern)
+                .cache_capacity(1)
+                .build_lazy()
+                .unwrap();
+
+            for text in texts {
+                let expected = eager.find_iter(text).collect::<Vec<_>>();
+
+                assert_eq!(lazy.find_iter(text).collect::<Vec<_>>(), expected);
+                assert_eq!(thrashing.find_iter(text).collect::<Vec<_>>(), expected);
+                assert_eq!(lazy.matches(text), eager.matches(text), "{pattern} {text}");
+                assert_eq!(thrashing.matches(text), eager.matches(text));
+            }
+        }
+
+        let lazy = LazyRegex::new("(a|b)*a(a|b){20}").unwrap();
+        let text = format!("{}a{}", "b".repeat(10), "ab".repeat(10));
+
+        assert!(lazy.matches(&text));
+        assert_eq!(lazy.find(&text).map(|m| m.range()), Some(0..31));
+        assert!(RegexBuilder::new("(a|b)*a(a|b){20}")
+            .dfa_state_limit(1_000)
+            .build()
+            .is_err());
+    }
+
+    // Compares the DFA and the lazy DFA with the regex crate on fixed and random patterns and
+    // on random texts over an alphabet mixing chars of every UTF-8 length.
+    #[test]
+    fn test_differential() {
+        let (patterns, texts) = parser::testing::cases();
+
+        for pattern in &patterns {
+            let expected = regex::Regex::new(pattern).unwrap();
+            let whole = regex::Regex::new(&format!("^(?:{pattern})$")).unwrap();
+            let dfa = Regex::new(pattern).unwrap();
+            let lazy = LazyRegex::new(pattern).unwrap();
+
+            for text in &texts {
+                let ranges = expected
+                    .find_iter(text)
+                    .map(|m| m.range())
+                    .collect::<Vec<_>>();
+
+                assert_eq!(
+                    dfa.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(),
+                    ranges,
+                    "{pattern:?} {text:?} DFA",
+                );
+                assert_eq!(
+                    lazy.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(),
+                    ranges,
+                    "{pattern:?} {text:?} lazy DFA",
+                );
+                assert_eq!(
+                    dfa.matches(text),
+                    whole.is_match(text),
+                    "{pattern:?} {text:?} DFA"
+                );
+                assert_eq!(
+                    lazy.matches(text),
+                    whole.is_match(text),
+                    "{pattern:?} {text:?} lazy DFA",
+                );
+            }
+        }
     }
 }
@@ -1,5 +1,12 @@
-use parser::Node;
-use std::collections::{HashMap, HashSet};
+use crate::{
+    format::{escape_dot, format_dot_node, format_ranges, format_states},
+    utf8, Budget, LimitError,
+};
+use parser::{increment, Assertion, Class, Node};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 struct Context {
@@ -18,13 +25,22 @@ impl Context {
     }
 }
 
-type Transitions = HashMap<usize, HashMap<Option<char>, HashSet<usize>>>;
+type Transitions = HashMap<usize, HashMap<Option<(char, char)>, Vec<usize>>>;
 
+/// A nondeterministic finite automaton, which a `RegexBuilder` builds from a pattern.
+//
+// The epsilon transitions of a state in `assertions` may only be followed where its
+// assertion holds, so they are kept even when the other epsilon transitions are removed.
+//
+// When `bytes` is set, the automaton reads bytes rather than chars, each byte standing for
+// the char of the same value.
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Automaton {
+pub struct Automaton {
     pub(crate) start: usize,
     pub(crate) accepts: HashSet<usize>,
     pub(crate) transitions: Transitions,
+    pub(crate) assertions: HashMap<usize, Assertion>,
+    pub(crate) bytes: bool,
 }
 
 impl Automaton {
@@ -33,155 +49,501 @@ impl Automaton {
             start,
             accepts,
             transitions: HashMap::new(),
+            assertions: HashMap::new(),
+            bytes: false,
         }
     }
 
-    fn add_transition(&mut self, from: usize, destination: usize, c: char) {
-        self.transitions
+    fn add_transition(&mut self, from: usize, destination: usize, range: (char, char)) {
+        let destinations = self
+            .transitions
             .entry(from)
             .or_default()
-            .entry(Some(c))
-            .or_default()
-            .insert(destination);
+            .entry(Some(range))
+            .or_default();
+        extend_unique(destinations, [destination]);
     }
 
     fn add_epsilon_transition(&mut self, from: usize, destination: usize) {
-        self.transitions
+        let destinations = self
+            .transitions
             .entry(from)
             .or_default()
             .entry(None)
-            .or_default()
-            .insert(destination);
+            .or_default();
+        extend_unique(destinations, [destination]);
     }
 
-    fn merge_transitions(&mut self, other: &Transitions) {
+    fn merge_transitions(&mut self, mut other: Transitions) {
+        if other.len() > self.transitions.len() {
+            std::mem::swap(&mut self.transitions, &mut other);
+        }
+
         for (from, transitions) in other {
-            for (c, destinations) in transitions {
-                self.transitions
-                    .entry(*from)
-                    .or_default()
-                    .entry(*c)
+            for (range, destinations) in transitions {
+                let merged = self
+                    .transitions
+                    .entry(from)
                     .or_default()
-                    .extend(destinations);
+                    .entry(range)
+                    .or_default();
+                extend_unique(merged, destinations);
+            }
+        }
+    }
+
+    /// Turns the automaton into one that reads the UTF-8 encodings of the chars it read.
+    /// With `allow_invalid_utf8`, a state that reads every non-ASCII char can also read any
+    /// single byte from 0x80 up, with a lower priority than a whole char.
+    pub(crate) fn into_bytes(mut self, allow_invalid_utf8: bool) -> Self {
+        let mut next = self.next_state();
+        let mut new_state = || {
+            next += 1;
+            next - 1
+        };
+        let transitions = std::mem::take(&mut self.transitions);
+
+        for (from, mut transitions) in transitions {
+            for destination in transitions.remove(&None).into_iter().flatten() {
+                self.add_epsilon_transition(from, destination);
+            }
+
+            let mut ranges: BTreeMap<Vec<usize>, Vec<(char, char)>> = BTreeMap::new();
+
+            for (range, destinations) in transitions {
+                ranges.entry(destinations).or_default().extend(range);
+            }
+
+            for (destinations, mut ranges) in ranges {
+                ranges.sort();
+
+                // Sequences sharing a prefix share the states reached by it.
+                let mut prefixes = HashMap::new();
+
+                for sequence in ranges
+                    .iter()
+                    .flat_map(|(start, end)| utf8::sequences(*start, *end))
+                {
+                    let (last, init) = sequence.split_last().unwrap();
+                    let mut state = from;
+
+                    for (i, range) in init.iter().enumerate() {
+                        let destination = *prefixes
+                            .entry(sequence[..=i].to_vec())
+                            .or_insert_with(&mut new_state);
+                        self.add_transition(state, destination, byte_range(*range));
+                        state = destination;
+                    }
+
+                    for destination in &destinations {
+                        self.add_transition(state, *destination, byte_range(*last));
+                    }
+                }
+
+                if allow_invalid_utf8 && covers_non_ascii(&ranges) {
+                    let invalid = new_state();
+                    self.add_epsilon_transition(from, invalid);
+
+                    for destination in &destinations {
+                        self.add_transition(invalid, *destination, ('\u{80}', '\u{FF}'));
+                    }
+                }
             }
         }
+
+        self.bytes = true;
+        self
     }
 
-    pub(crate) fn remove_epsilon_transitions(&mut self) {
+    /// Returns the automaton that also reads any text before what this one reads, skipping
+    /// as little of it as it can, so that a leftmost-first DFA of it finds the end of the
+    /// leftmost-first match in a single pass.
+    pub(crate) fn unanchored(&self) -> Self {
+        let start = self.next_state();
+        let skip = start + 1;
+        let any = match self.bytes {
+            true => ('\0', '\u{FF}'),
+            false => ('\0', char::MAX),
+        };
+
+        let mut automaton = self.clone();
+        automaton.start = start;
+        automaton.add_epsilon_transition(start, self.start);
+        automaton.add_epsilon_transition(start, skip);
+        automaton.add_transition(skip, start, any);
+
+        automaton
+    }
+
+    /// Returns the automaton that reads the reverses of the texts this one reads, with the
+    /// assertions looking the other way. The epsilon transitions must not be removed yet, so
+    /// that the assertions are still only reached by them.
+    pub(crate) fn reverse(&self) -> Self {
+        let start = self.next_state();
+        let accept = start + 1;
+
+        let mut automaton = Automaton::new(start, [accept].into());
+        automaton.bytes = self.bytes;
+
+        let mut accepts = Vec::from_iter(&self.accepts);
+        accepts.sort();
+
+        for from in accepts {
+            automaton.add_epsilon_transition(start, *from);
+        }
+
+        for (from, transitions) in &self.transitions {
+            for (range, destinations) in transitions {
+                for destination in destinations {
+                    match range {
+                        Some(range) => automaton.add_transition(*destination, *from, *range),
+                        None => automaton.add_epsilon_transition(*destination, *from),
+                    }
+                }
+            }
+        }
+
+        // The start may hold an assertion, which is then checked on the way to the accept.
+        automaton.add_epsilon_transition(self.start, accept);
+        automaton.assertions = self
+            .assertions
+            .iter()
+            .map(|(state, assertion)| {
+                let assertion = match assertion {
+                    Assertion::StartLine => Assertion::EndLine,
+                    Assertion::EndLine => Assertion::StartLine,
+                    Assertion::StartText => Assertion::EndText,
+                    Assertion::EndText => Assertion::StartText,
+                    assertion => *assertion,
+                };
+
+                (*state, assertion)
+            })
+            .collect();
+
+        automaton
+    }
+
+    // Returns a state above those of the automaton.
+    fn next_state(&self) -> usize {
+        self.transitions
+            .iter()
+            .flat_map(|(from, transitions)| transitions.values().flatten().chain([from]))
+            .chain(&self.accepts)
+            .fold(self.start, |max, state| max.max(*state))
+            + 1
+    }
+
+    /// Replaces the epsilon transitions with the char transitions they lead to, except those
+    /// of the assertions.
+    pub fn remove_epsilon_transitions(&mut self) {
+        self.remove_epsilon_transitions_within(&mut Budget::new(usize::MAX))
+            .expect("an unlimited budget never runs out");
+    }
+
+    /// Like `remove_epsilon_transitions`, but fails once it has spent the budget, leaving
+    /// the automaton as it was.
+    pub(crate) fn remove_epsilon_transitions_within(
+        &mut self,
+        budget: &mut Budget,
+    ) -> Result<(), LimitError> {
+        let transitions = self.new_transitions_without_epsilon_transitions(budget)?;
+
         if self
-            .accepts
-            .is_subset(&self.calc_epsilon_closure(self.start))
+            .calc_epsilon_closure(self.start)
+            .iter()
+            .any(|destination| self.accepts.contains(destination))
         {
             self.accepts.insert(self.start);
         }
 
-        self.transitions = self.new_transitions_without_epsilon_transitions();
+        self.transitions = transitions;
+        Ok(())
     }
 
-    fn new_transitions_without_epsilon_transitions(&self) -> Transitions {
+    fn new_transitions_without_epsilon_transitions(
+        &self,
+        budget: &mut Budget,
+    ) -> Result<Transitions, LimitError> {
         let mut new_transitions: Transitions = HashMap::new();
+        let mut closures = HashMap::new();
+        let mut closure = |from: usize| -> Vec<usize> {
+            closures
+                .entry(from)
+                .or_insert_with(|| self.calc_epsilon_closure(from))
+                .clone()
+        };
 
         for from in self.transitions.keys().cloned() {
-            let chars = self.calc_chars_without_epsilon_transitions(None);
-            let mut chars = Vec::from_iter(chars);
-            chars.sort();
+            if self.assertions.contains_key(&from) {
+                for destination in self.calc_destinations(from, None) {
+                    let new_destinations = new_transitions
+                        .entry(from)
+                        .or_default()
+                        .entry(None)
+                        .or_default();
+                    extend_unique(new_destinations, closure(*destination));
+                }
+            }
 
-            for c in chars {
-                for destination in self.calc_epsilon_closure(from) {
-                    let destinations = self.expand_epsilon_closure(destination, Some(c));
+            // The closures of the states in the closure of `from` are within it, so its
+            // states list the destinations of each range in priority order.
+            let mut seen: HashMap<(char, char), HashSet<usize>> = HashMap::new();
+            let states = closure(from);
+            budget.spend(states.len())?;
 
-                    if destinations.is_empty() {
+            // The closure stops at the assertions, which are only checked when matching, so
+            // they are still reached by epsilon transitions.
+            for state in &states {
+                if *state != from && self.assertions.contains_key(state) {
+                    let new_destinations = new_transitions
+                        .entry(from)
+                        .or_default()
+                        .entry(None)
+                        .or_default();
+                    extend_unique(new_destinations, [*state]);
+                }
+            }
+
+            for state in states {
+                let Some(transitions) = self.transitions.get(&state) else {
+                    continue;
+                };
+
+                for (range, destinations) in transitions {
+                    let Some(range) = range else {
                         continue;
-                    }
+                    };
 
-                    new_transitions
+                    let seen = seen.entry(*range).or_default();
+                    let new_destinations = new_transitions
                         .entry(from)
                         .or_default()
-                        .entry(Some(c))
-                        .or_default()
-                        .extend(destinations);
+                        .entry(Some(*range))
+                        .or_default();
+
+                    for next in destinations {
+                        let destinations = closure(*next);
+                        budget.spend(destinations.len())?;
+                        new_destinations.extend(
+                            destinations
+                                .into_iter()
+                                .filter(|destination| seen.insert(*destination)),
+                        );
+                    }
                 }
             }
         }
 
-        new_transitions
+        Ok(new_transitions)
     }
 
-    fn expand_epsilon_closure(&self, from: usize, c: Option<char>) -> HashSet<usize> {
-        let first_destinations = self.calc_epsilon_closure(from);
-        let mut second_destinations = HashSet::new();
-        let mut final_destinations = HashSet::new();
-
-        for destination in first_destinations {
-            second_destinations.extend(self.calc_destinations(destination, c));
-        }
-
-        for destination in second_destinations {
-            final_destinations.extend(self.calc_epsilon_closure(destination));
-        }
+    pub(crate) fn calc_epsilon_closure(&self, from: usize) -> Vec<usize> {
+        self.calc_closure(from, None)
+    }
 
-        final_destinations
+    /// Like `calc_epsilon_closure`, but also passes the assertions that hold between
+    /// `behind` and `ahead`.
+    pub(crate) fn calc_look_closure(
+        &self,
+        from: usize,
+        behind: Option<char>,
+        ahead: Option<char>,
+    ) -> Vec<usize> {
+        self.calc_closure(from, Some((behind, ahead)))
     }
 
-    fn calc_epsilon_closure(&self, from: usize) -> HashSet<usize> {
-        let mut epsilon_closure = HashSet::new();
-        epsilon_closure.insert(from);
-        let mut modified = true;
+    // The closure is listed in priority order: epsilon transitions are followed
+    // depth-first in the order they were added, which is the order in which a
+    // backtracking matcher would try them.
+    fn calc_closure(&self, from: usize, look: Option<(Option<char>, Option<char>)>) -> Vec<usize> {
+        let mut epsilon_closure = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
 
-        while modified {
-            let mut temp_epsilon_closure = HashSet::<usize>::new();
-            modified = false;
+        while let Some(destination) = stack.pop() {
+            if !visited.insert(destination) {
+                continue;
+            }
 
-            for destination in &epsilon_closure {
-                let destinations = self.calc_destinations(*destination, None);
+            epsilon_closure.push(destination);
 
-                if destinations.is_empty()
-                    || epsilon_closure
-                        .union(&temp_epsilon_closure)
-                        .cloned()
-                        .collect::<HashSet<_>>()
-                        .is_superset(&destinations)
-                {
-                    continue;
-                }
+            let open = match (self.assertions.get(&destination), look) {
+                (None, _) => true,
+                (Some(assertion), Some((behind, ahead))) => assertion.is_satisfied(behind, ahead),
+                (Some(_), None) => false,
+            };
 
-                temp_epsilon_closure.extend(destinations);
-                modified = true;
+            if open {
+                stack.extend(self.calc_destinations(destination, None).iter().rev());
             }
-
-            epsilon_closure.extend(temp_epsilon_closure);
         }
 
         epsilon_closure
     }
 
-    pub(crate) fn calc_destinations(&self, from: usize, c: Option<char>) -> HashSet<usize> {
+    pub(crate) fn calc_destinations(&self, from: usize, range: Option<(char, char)>) -> &[usize] {
         self.transitions
             .get(&from)
-            .cloned()
-            .unwrap_or_default()
-            .get(&c)
-            .cloned()
+            .and_then(|transitions| transitions.get(&range))
+            .map(Vec::as_slice)
             .unwrap_or_default()
     }
 
-    pub(crate) fn calc_chars_without_epsilon_transitions(
+    pub(crate) fn calc_ranges_without_epsilon_transitions(
         &self,
-        destinations: Option<&HashSet<usize>>,
-    ) -> HashSet<char> {
-        self.transitions
+        destinations: Option<&[usize]>,
+    ) -> HashSet<(char, char)> {
+        match destinations {
+            Some(destinations) => destinations
+                .iter()
+                .filter_map(|from| self.transitions.get(from))
+                .flat_map(|transitions| transitions.keys().flatten())
+                .cloned()
+                .collect(),
+            None => self
+                .transitions
+                .values()
+                .flat_map(|transitions| transitions.keys().flatten())
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl Automaton {
+    /// Returns the automaton in the DOT language of Graphviz. The start state is drawn as a
+    /// box and the accept states with a double outline. The transitions to a destination
+    /// are drawn as an edge labelled with the class of their chars, and the epsilon
+    /// transitions as edges labelled ε.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph NFA {\n    rankdir=LR;\n    node [shape=circle];\n");
+
+        for state in self.states() {
+            let mut attributes = Vec::new();
+
+            if state == self.start {
+                attributes.push("shape=box".to_string());
+            }
+
+            if self.accepts.contains(&state) {
+                attributes.push("peripheries=2".to_string());
+            }
+
+            if let Some(assertion) = self.assertions.get(&state) {
+                let assertion = escape_dot(&assertion.to_string());
+                attributes.push(format!(r#"label="{state}\n{assertion}""#));
+            }
+
+            dot.push_str(&format_dot_node(state, &attributes));
+        }
+
+        for from in self.states() {
+            for destination in self.calc_destinations(from, None) {
+                dot.push_str(&format!("    {from} -> {destination} [label=\"ε\"];\n"));
+            }
+
+            for (ranges, destination) in self.char_transitions(from) {
+                let label = escape_dot(&format_ranges(&ranges, self.bytes));
+                dot.push_str(&format!(
+                    "    {from} -> {destination} [label=\"{label}\"];\n"
+                ));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn states(&self) -> Vec<usize> {
+        let mut states = self
+            .transitions
             .iter()
-            .filter(|(from, _)| {
-                destinations
-                    .map(|destinations| destinations.contains(from))
-                    .unwrap_or(true)
-            })
-            .flat_map(|(_, transitions)| transitions.keys().flatten())
+            .flat_map(|(from, transitions)| transitions.values().flatten().chain([from]))
+            .chain(&self.accepts)
+            .chain([&self.start])
             .cloned()
+            .collect::<Vec<_>>();
+        states.sort();
+        states.dedup();
+        states
+    }
+
+    // Returns the char transitions from a state with their ranges grouped by destination, in
+    // the order of the destinations.
+    fn char_transitions(&self, from: usize) -> Vec<(Vec<(char, char)>, usize)> {
+        let mut ranges: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+
+        for (range, destinations) in self.transitions.get(&from).into_iter().flatten() {
+            if let Some(range) = range {
+                for destination in destinations {
+                    ranges.entry(*destination).or_default().push(*range);
+                }
+            }
+        }
+
+        ranges
+            .into_iter()
+            .map(|(destination, ranges)| (Class::new(ranges).ranges().to_vec(), destination))
             .collect()
     }
 }
 
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut accepts = Vec::from_iter(&self.accepts);
+        accepts.sort();
+
+        writeln!(f, "start: {}", self.start)?;
+        writeln!(f, "accepts: {accepts:?}")?;
+
+        for from in self.states() {
+            match self.assertions.get(&from) {
+                Some(assertion) => writeln!(f, "{from}: {assertion}")?,
+                None => writeln!(f, "{from}:")?,
+            }
+
+            let epsilon_destinations = self.calc_destinations(from, None);
+
+            if !epsilon_destinations.is_empty() {
+                writeln!(f, "  ε => {}", format_states(epsilon_destinations))?;
+            }
+
+            let mut destinations: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+            for (ranges, destination) in self.char_transitions(from) {
+                destinations.entry(ranges).or_default().push(destination);
+            }
+
+            for (ranges, destinations) in destinations {
+                let ranges = format_ranges(&ranges, self.bytes);
+                writeln!(f, "  {ranges} => {}", format_states(&destinations))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn byte_range((start, end): (u8, u8)) -> (char, char) {
+    (char::from(start), char::from(end))
+}
+
+// Returns true if the sorted ranges contain every char from U+0080 up.
+fn covers_non_ascii(ranges: &[(char, char)]) -> bool {
+    let mut next = Some('\u{80}');
+
+    for (start, end) in ranges {
+        match next {
+            Some(c) if *start > c => return false,
+            Some(c) if *end >= c => next = increment(*end),
+            _ => {}
+        }
+    }
+
+    next.is_none()
+}
+
 impl From<Node> for Automaton {
     fn from(node: Node) -> Self {
         node.assemble(&mut Context::new())
@@ -211,80 +573,287 @@ impl Assembler for Node {
                 let accepts = [accept].into();
 
                 let mut automaton = Automaton::new(start, accepts);
-                automaton.add_transition(start, accept, *c);
+                automaton.add_transition(start, accept, (*c, *c));
 
                 automaton
             }
-            Node::Concat(left, right) => {
-                let left = left.assemble(context);
-                let right = right.assemble(context);
-                let start = left.start;
-                let accepts = right.accepts.clone();
-
-                let mut automaton = Automaton::new(start, accepts);
-                automaton.merge_transitions(&left.transitions);
-                automaton.merge_transitions(&right.transitions);
+            // Chains of concatenations and unions are assembled in a loop, as they nest as
+            // deeply as they are long.
+            Node::Concat(..) => self
+                .concat_items()
+                .into_iter()
+                .map(|item| item.assemble(context))
+                .reduce(Automaton::concat)
+                .unwrap(),
+            Node::Union(..) => {
+                let mut alternatives = self
+                    .alternatives()
+                    .into_iter()
+                    .map(|alternative| alternative.assemble(context))
+                    .collect::<Vec<_>>();
+                let mut automaton = alternatives.pop().unwrap();
 
-                for accept in left.accepts {
-                    let start = accept;
-                    let accept = right.start;
-                    automaton.add_epsilon_transition(start, accept);
+                while let Some(left) = alternatives.pop() {
+                    automaton = Automaton::union(left, automaton, context);
                 }
 
                 automaton
             }
-            Node::Union(left, right) => {
-                let left = left.assemble(context);
-                let right = right.assemble(context);
-                let start = context.next();
-                let accept = context.next();
-                let accepts = [accept].into();
+            Node::Star(node) => {
+                let inner = node.assemble(context);
+                Automaton::star(inner, true, context)
+            }
+            Node::Group(_, _, node) => node.assemble(context),
+            Node::Class(class) => Automaton::class(class, context),
+            Node::Any { newline } => Automaton::class(&Class::any(*newline), context),
+            Node::Assert(assertion) => {
+                let mut automaton = Node::Empty.assemble(context);
+                automaton.assertions.insert(automaton.start, *assertion);
 
-                let mut automaton = Automaton::new(start, accepts);
-                automaton.add_epsilon_transition(start, left.start);
-                automaton.add_epsilon_transition(start, right.start);
-                automaton.merge_transitions(&left.transitions);
-                automaton.merge_transitions(&right.transitions);
-
-                for left_accept in left.accepts {
-                    let start = left_accept;
-                    automaton.add_epsilon_transition(start, accept);
+                automaton
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                let mut automaton = None;
+
+                // `x{n,}` loops back into its last copy of `x`, which gives the empty
+                // iterations of `x` the priority a backtracking matcher would.
+                let copies = match max {
+                    None => min.saturating_sub(1),
+                    Some(_) => *min,
+                };
+
+                for _ in 0..copies {
+                    let inner = node.assemble(context);
+                    automaton = Some(Automaton::concat_optional(automaton, inner));
                 }
 
-                for right_accept in right.accepts {
-                    let start = right_accept;
-                    automaton.add_epsilon_transition(start, accept);
+                let rest = match max {
+                    None if *min > 0 => {
+                        let inner = node.assemble(context);
+                        Some(Automaton::plus(inner, *greedy, context))
+                    }
+                    None => {
+                        let inner = node.assemble(context);
+                        Some(Automaton::star(inner, *greedy, context))
+                    }
+                    Some(max) => {
+                        let mut rest = None;
+
+                        for _ in *min..*max {
+                            let inner = node.assemble(context);
+                            let inner = match rest {
+                                Some(rest) => Automaton::concat(inner, rest),
+                                None => inner,
+                            };
+                            rest = Some(Automaton::optional(inner, *greedy, context));
+                        }
+
+                        rest
+                    }
+                };
+
+                if let Some(rest) = rest {
+                    automaton = Some(Automaton::concat_optional(automaton, rest));
                 }
 
-                automaton
+                automaton.unwrap_or_else(|| Node::Empty.assemble(context))
             }
-            Node::Star(node) => {
-                let inner = node.assemble(context);
-                let start = context.next();
-                let accept = context.next();
-                let accepts = inner.accepts.union(&[accept].into()).cloned().collect();
+        }
+    }
+}
 
-                let mut automaton = Automaton::new(start, accepts);
-                automaton.add_epsilon_transition(start, inner.start);
-                automaton.add_epsilon_transition(start, accept);
-                automaton.merge_transitions(&inner.transitions);
+impl Automaton {
+    fn concat(left: Automaton, right: Automaton) -> Automaton {
+        let start = left.start;
+        let accepts = right.accepts.clone();
 
-                for accept in inner.accepts {
-                    let start = accept;
-                    let accept = inner.start;
-                    automaton.add_epsilon_transition(start, accept);
-                }
+        let mut automaton = Automaton::new(start, accepts);
+        automaton.merge_transitions(left.transitions);
+        automaton.assertions.extend(left.assertions);
+        automaton.merge_transitions(right.transitions);
+        automaton.assertions.extend(right.assertions);
 
-                automaton
+        for accept in left.accepts {
+            let start = accept;
+            let accept = right.start;
+            automaton.add_epsilon_transition(start, accept);
+        }
+
+        automaton
+    }
+
+    fn union(left: Automaton, right: Automaton, context: &mut Context) -> Automaton {
+        let start = context.next();
+        let accept = context.next();
+        let accepts = [accept].into();
+
+        let mut automaton = Automaton::new(start, accepts);
+        automaton.add_epsilon_transition(start, left.start);
+        automaton.add_epsilon_transition(start, right.start);
+        automaton.merge_transitions(left.transitions);
+        automaton.assertions.extend(left.assertions);
+        automaton.merge_transitions(right.transitions);
+        automaton.assertions.extend(right.assertions);
+
+        for left_accept in left.accepts {
+            let start = left_accept;
+            automaton.add_epsilon_transition(start, accept);
+        }
+
+        for right_accept in right.accepts {
+            let start = right_accept;
+            automaton.add_epsilon_transition(start, accept);
+        }
+
+        automaton
+    }
+
+    fn class(class: &Class, context: &mut Context) -> Automaton {
+        let start = context.next();
+        let accept = context.next();
+        let accepts = [accept].into();
+
+        let mut automaton = Automaton::new(start, accepts);
+
+        for range in class.ranges() {
+            automaton.add_transition(start, accept, *range);
+        }
+
+        automaton
+    }
+
+    fn concat_optional(left: Option<Automaton>, right: Automaton) -> Automaton {
+        match left {
+            Some(left) => Automaton::concat(left, right),
+            None => right,
+        }
+    }
+
+    fn star(inner: Automaton, greedy: bool, context: &mut Context) -> Automaton {
+        let start = context.next();
+        let accept = context.next();
+        let accepts = [accept].into();
+
+        let mut automaton = Automaton::new(start, accepts);
+        automaton.add_prioritized_epsilon_transitions(start, inner.start, accept, greedy);
+        automaton.merge_transitions(inner.transitions);
+        automaton.assertions.extend(inner.assertions);
+
+        for inner_accept in inner.accepts {
+            let start = inner_accept;
+            automaton.add_prioritized_epsilon_transitions(start, inner.start, accept, greedy);
+        }
+
+        automaton
+    }
+
+    fn plus(inner: Automaton, greedy: bool, context: &mut Context) -> Automaton {
+        let start = inner.start;
+        let accept = context.next();
+        let accepts = [accept].into();
+
+        let mut automaton = Automaton::new(start, accepts);
+        automaton.merge_transitions(inner.transitions);
+        automaton.assertions.extend(inner.assertions);
+
+        for inner_accept in inner.accepts {
+            let start = inner_accept;
+            automaton.add_prioritized_epsilon_transitions(start, inner.start, accept, greedy);
+        }
+
+        automaton
+    }
+
+    fn optional(inner: Automaton, greedy: bool, context: &mut Context) -> Automaton {
+        let start = context.next();
+        let accept = context.next();
+        let accepts = [accept].into();
+
+        let mut automaton = Automaton::new(start, accepts);
+        automaton.add_prioritized_epsilon_transitions(start, inner.start, accept, greedy);
+        automaton.merge_transitions(inner.transitions);
+        automaton.assertions.extend(inner.assertions);
+
+        for inner_accept in inner.accepts {
+            let start = inner_accept;
+            automaton.add_epsilon_transition(start, accept);
+        }
+
+        automaton
+    }
+
+    fn add_prioritized_epsilon_transitions(
+        &mut self,
+        from: usize,
+        inner: usize,
+        skip: usize,
+        greedy: bool,
+    ) {
+        if greedy {
+            self.add_epsilon_transition(from, inner);
+            self.add_epsilon_transition(from, skip);
+        } else {
+            self.add_epsilon_transition(from, skip);
+            self.add_epsilon_transition(from, inner);
+        }
+    }
+}
+
+/// Returns the number of states `Automaton::from` would make for the node, without making
+/// them, saturating at `usize::MAX`.
+pub(crate) fn count_states(node: &Node) -> usize {
+    match node {
+        Node::Empty | Node::Char(_) | Node::Class(_) | Node::Any { .. } | Node::Assert(_) => 2,
+        Node::Concat(..) => node
+            .concat_items()
+            .into_iter()
+            .map(count_states)
+            .fold(0, usize::saturating_add),
+        // Each union but the innermost adds a start and an accept state.
+        Node::Union(..) => node
+            .alternatives()
+            .into_iter()
+            .map(|alternative| count_states(alternative).saturating_add(2))
+            .fold(0, usize::saturating_add)
+            .saturating_sub(2),
+        Node::Star(node) => count_states(node).saturating_add(2),
+        Node::Group(_, _, node) => count_states(node),
+        Node::Repeat { node, min, max, .. } => {
+            let inner = count_states(node);
+            let states = match max {
+                None if *min > 0 => min.saturating_mul(inner).saturating_add(1),
+                None => inner.saturating_add(2),
+                Some(max) => min
+                    .saturating_mul(inner)
+                    .saturating_add((max - min).saturating_mul(inner.saturating_add(2))),
+            };
+
+            match states {
+                0 => 2,
+                states => states,
             }
         }
     }
 }
 
+pub(crate) fn extend_unique(destinations: &mut Vec<usize>, other: impl IntoIterator<Item = usize>) {
+    for destination in other {
+        if !destinations.contains(&destination) {
+            destinations.push(destination);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::nondeterministic_finite_automaton::Automaton;
-    use parser::Node;
+    use crate::nondeterministic_finite_automaton::{count_states, Automaton};
+    use parser::{parse, Assertion, Node};
+    use std::collections::HashSet;
 
     #[test]
     fn test_from_node() {
@@ -294,6 +863,8 @@ mod tests {
                 start: 0,
                 accepts: [1].into(),
                 transitions: [(0, [(None, [1].into())].into())].into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
 
@@ -302,7 +873,9 @@ mod tests {
             Automaton {
                 start: 0,
                 accepts: [1].into(),
-                transitions: [(0, [(Some('a'), [1].into())].into())].into(),
+                transitions: [(0, [(Some(('a', 'a')), [1].into())].into())].into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
 
@@ -315,11 +888,13 @@ mod tests {
                 start: 0,
                 accepts: [3].into(),
                 transitions: [
-                    (0, [(Some('a'), [1].into())].into()),
+                    (0, [(Some(('a', 'a')), [1].into())].into()),
                     (1, [(None, [2].into())].into()),
-                    (2, [(Some('b'), [3].into())].into()),
+                    (2, [(Some(('b', 'b')), [3].into())].into()),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
 
@@ -333,12 +908,14 @@ mod tests {
                 accepts: [5].into(),
                 transitions: [
                     (4, [(None, [0, 2].into())].into()),
-                    (0, [(Some('a'), [1].into())].into()),
-                    (2, [(Some('b'), [3].into())].into()),
+                    (0, [(Some(('a', 'a')), [1].into())].into()),
+                    (2, [(Some(('b', 'b')), [3].into())].into()),
                     (1, [(None, [5].into())].into()),
                     (3, [(None, [5].into())].into()),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
 
@@ -346,13 +923,15 @@ mod tests {
             Automaton::from(Node::Star(Box::new(Node::Char('a')))),
             Automaton {
                 start: 2,
-                accepts: [1, 3].into(),
+                accepts: [3].into(),
                 transitions: [
                     (2, [(None, [0, 3].into())].into()),
-                    (0, [(Some('a'), [1].into())].into()),
-                    (1, [(None, [0].into())].into()),
+                    (0, [(Some(('a', 'a')), [1].into())].into()),
+                    (1, [(None, [0, 3].into())].into()),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
     }
@@ -363,11 +942,19 @@ mod tests {
             start: 0,
             accepts: [2].into(),
             transitions: [
-                (0, [(Some('a'), [0].into()), (None, [1].into())].into()),
-                (1, [(Some('b'), [1].into()), (None, [2].into())].into()),
-                (2, [(Some('c'), [2].into())].into()),
+                (
+                    0,
+                    [(Some(('a', 'a')), [0].into()), (None, [1].into())].into(),
+                ),
+                (
+                    1,
+                    [(Some(('b', 'b')), [1].into()), (None, [2].into())].into(),
+                ),
+                (2, [(Some(('c', 'c')), [2].into())].into()),
             ]
             .into(),
+            assertions: [].into(),
+            bytes: false,
         };
         automaton.remove_epsilon_transitions();
 
@@ -380,19 +967,25 @@ mod tests {
                     (
                         0,
                         [
-                            (Some('a'), [0, 1, 2].into()),
-                            (Some('b'), [1, 2].into()),
-                            (Some('c'), [2].into())
+                            (Some(('a', 'a')), [0, 1, 2].into()),
+                            (Some(('b', 'b')), [1, 2].into()),
+                            (Some(('c', 'c')), [2].into())
                         ]
                         .into()
                     ),
                     (
                         1,
-                        [(Some('b'), [1, 2].into()), (Some('c'), [2].into())].into()
+                        [
+                            (Some(('b', 'b')), [1, 2].into()),
+                            (Some(('c', 'c')), [2].into())
+                        ]
+                        .into()
                     ),
-                    (2, [(Some('c'), [2].into())].into()),
+                    (2, [(Some(('c', 'c')), [2].into())].into()),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
             },
         );
 
@@ -400,11 +993,16 @@ mod tests {
             start: 0,
             accepts: [3, 4].into(),
             transitions: [
-                (0, [(Some('a'), [1].into())].into()),
-                (1, [(None, [2].into()), (Some('b'), [1, 3].into())].into()),
-                (2, [(Some('a'), [4].into())].into()),
+                (0, [(Some(('a', 'a')), [1].into())].into()),
+                (
+                    1,
+                    [(None, [2].into()), (Some(('b', 'b')), [1, 3].into())].into(),
+                ),
+                (2, [(Some(('a', 'a')), [4].into())].into()),
             ]
             .into(),
+            assertions: [].into(),
+            bytes: false,
         };
         automaton.remove_epsilon_transitions();
 
@@ -414,15 +1012,200 @@ mod tests {
                 start: 0,
                 accepts: [3, 4].into(),
                 transitions: [
-                    (0, [(Some('a'), [1, 2].into())].into()),
+                    (0, [(Some(('a', 'a')), [1, 2].into())].into()),
                     (
                         1,
-                        [(Some('a'), [4].into()), (Some('b'), [1, 2, 3].into())].into()
+                        [
+                            (Some(('a', 'a')), [4].into()),
+                            (Some(('b', 'b')), [1, 2, 3].into())
+                        ]
+                        .into()
                     ),
-                    (2, [(Some('a'), [4].into())].into()),
+                    (2, [(Some(('a', 'a')), [4].into())].into()),
                 ]
                 .into(),
+                assertions: [].into(),
+                bytes: false,
+            },
+        );
+    }
+
+    #[test]
+    fn test_from_repeat() {
+        assert_eq!(
+            Automaton::from(Node::Repeat {
+                node: Box::new(Node::Char('a')),
+                min: 1,
+                max: Some(2),
+                greedy: false,
+            }),
+            Automaton {
+                start: 0,
+                accepts: [5].into(),
+                transitions: [
+                    (0, [(Some(('a', 'a')), [1].into())].into()),
+                    (1, [(None, [4].into())].into()),
+                    (2, [(Some(('a', 'a')), [3].into())].into()),
+                    (3, [(None, [5].into())].into()),
+                    (4, [(None, [5, 2].into())].into()),
+                ]
+                .into(),
+                assertions: [].into(),
+                bytes: false,
+            },
+        );
+
+        assert_eq!(
+            Automaton::from(Node::Repeat {
+                node: Box::new(Node::Char('a')),
+                min: 0,
+                max: Some(0),
+                greedy: true,
+            }),
+            Automaton::from(Node::Empty),
+        );
+    }
+
+    #[test]
+    fn test_from_assertion() {
+        assert_eq!(
+            Automaton::from(Node::Assert(Assertion::StartText)),
+            Automaton {
+                start: 0,
+                accepts: [1].into(),
+                transitions: [(0, [(None, [1].into())].into())].into(),
+                assertions: [(0, Assertion::StartText)].into(),
+                bytes: false,
             },
         );
+
+        let automaton = Automaton::from(parse(r"^a").unwrap());
+
+        assert_eq!(automaton.calc_epsilon_closure(0), vec![0]);
+        assert_eq!(
+            automaton.calc_look_closure(0, None, Some('a')),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            automaton.calc_look_closure(0, Some('b'), Some('a')),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let mut automaton = Automaton::from(parse("a|b*").unwrap());
+
+        assert_eq!(
+            automaton.to_string(),
+            concat!(
+                "start: 6\n",
+                "accepts: [7]\n",
+                "0:\n  [a] => 1\n",
+                "1:\n  ε => 7\n",
+                "2:\n  [b] => 3\n",
+                "3:\n  ε => 2, 5\n",
+                "4:\n  ε => 2, 5\n",
+                "5:\n  ε => 7\n",
+                "6:\n  ε => 0, 4\n",
+                "7:\n",
+            ),
+        );
+
+        automaton.remove_epsilon_transitions();
+
+        assert_eq!(
+            automaton.to_string(),
+            concat!(
+                "start: 6\n",
+                "accepts: [6, 7]\n",
+                "0:\n  [a] => 1, 7\n",
+                "1:\n",
+                "2:\n  [b] => 2, 3, 5, 7\n",
+                "3:\n  [b] => 2, 3, 5, 7\n",
+                "4:\n  [b] => 2, 3, 5, 7\n",
+                "5:\n",
+                "6:\n  [a] => 1\n  [ab] => 7\n  [b] => 2, 3, 5\n",
+                "7:\n",
+            ),
+        );
+
+        let automaton = Automaton::from(parse(r"\bé").unwrap()).into_bytes(false);
+
+        assert_eq!(
+            automaton.to_string(),
+            concat!(
+                "start: 0\n",
+                "accepts: [3]\n",
+                "0: \\b\n  ε => 1\n",
+                "1:\n  ε => 2\n",
+                "2:\n  [\\xC3] => 4\n",
+                "3:\n",
+                "4:\n  [\\xA9] => 3\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            Automaton::from(parse(r"\ba|é*").unwrap()).to_dot(),
+            concat!(
+                "digraph NFA {\n",
+                "    rankdir=LR;\n",
+                "    node [shape=circle];\n",
+                "    0 [label=\"0\\n\\\\b\"];\n",
+                "    8 [shape=box];\n",
+                "    9 [peripheries=2];\n",
+                "    0 -> 1 [label=\"ε\"];\n",
+                "    1 -> 2 [label=\"ε\"];\n",
+                "    2 -> 3 [label=\"[a]\"];\n",
+                "    3 -> 9 [label=\"ε\"];\n",
+                "    4 -> 5 [label=\"[é]\"];\n",
+                "    5 -> 4 [label=\"ε\"];\n",
+                "    5 -> 7 [label=\"ε\"];\n",
+                "    6 -> 4 [label=\"ε\"];\n",
+                "    6 -> 7 [label=\"ε\"];\n",
+                "    7 -> 9 [label=\"ε\"];\n",
+                "    8 -> 0 [label=\"ε\"];\n",
+                "    8 -> 6 [label=\"ε\"];\n",
+                "}\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_count_states() {
+        let states = |automaton: &Automaton| {
+            let mut states = HashSet::from([automaton.start]);
+            states.extend(&automaton.accepts);
+
+            for (from, transitions) in &automaton.transitions {
+                states.insert(*from);
+                states.extend(transitions.values().flatten());
+            }
+
+            states.len()
+        };
+
+        for pattern in [
+            "",
+            "a",
+            "abc",
+            "a|b|c",
+            "(ab|c*)d?",
+            "a{3}",
+            "a{2,}",
+            "(a|b){0,3}?",
+            "a{0}",
+            r"^\bx+$",
+        ] {
+            let node = parse(pattern).unwrap();
+            let expected = count_states(&node);
+
+            assert_eq!(states(&Automaton::from(node)), expected, "{pattern}");
+        }
+
+        assert_eq!(count_states(&parse("(a{1000}){1000}").unwrap()), 2_000_000);
     }
 }
@@ -0,0 +1,100 @@
+/// Splits the chars from `start` to `end` into sequences of byte ranges, so that the UTF-8
+/// encodings of the chars are exactly the byte strings matched by one of the sequences.
+/// The sequences are listed in the order of the chars they match.
+pub(crate) fn sequences(start: char, end: char) -> Vec<Vec<(u8, u8)>> {
+    let mut sequences = Vec::new();
+    let mut stack = vec![(start as u32, end as u32)];
+
+    'split: while let Some((start, end)) = stack.pop() {
+        // Surrogates are not chars, so they have no encoding.
+        if start < 0xD800 && end > 0xDFFF {
+            stack.extend([(0xE000, end), (start, 0xD7FF)]);
+            continue;
+        }
+
+        // The encodings of a sequence all have the same length.
+        for max in [0x7F, 0x7FF, 0xFFFF] {
+            if start <= max && max < end {
+                stack.extend([(max + 1, end), (start, max)]);
+                continue 'split;
+            }
+        }
+
+        // Each continuation byte has to range over all its values, unless the bytes before
+        // it are the same in both encodings.
+        for i in 1..4 {
+            let mask = (1 << (6 * i)) - 1;
+
+            if start & !mask != end & !mask {
+                if start & mask != 0 {
+                    stack.extend([((start | mask) + 1, end), (start, start | mask)]);
+                    continue 'split;
+                }
+
+                if end & mask != mask {
+                    stack.extend([(end & !mask, end), (start, (end & !mask) - 1)]);
+                    continue 'split;
+                }
+            }
+        }
+
+        let mut first = [0; 4];
+        let mut last = [0; 4];
+        let first = char::from_u32(start).unwrap().encode_utf8(&mut first);
+        let last = char::from_u32(end).unwrap().encode_utf8(&mut last);
+
+        sequences.push(first.bytes().zip(last.bytes()).collect::<Vec<_>>());
+    }
+
+    sequences
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utf8::sequences;
+
+    #[test]
+    fn test_sequences() {
+        assert_eq!(
+            sequences('\0', char::MAX),
+            vec![
+                vec![(0x00, 0x7F)],
+                vec![(0xC2, 0xDF), (0x80, 0xBF)],
+                vec![(0xE0, 0xE0), (0xA0, 0xBF), (0x80, 0xBF)],
+                vec![(0xE1, 0xEC), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xED, 0xED), (0x80, 0x9F), (0x80, 0xBF)],
+                vec![(0xEE, 0xEF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF0, 0xF0), (0x90, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF1, 0xF3), (0x80, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF4, 0xF4), (0x80, 0x8F), (0x80, 0xBF), (0x80, 0xBF)],
+            ],
+        );
+        assert_eq!(sequences('a', 'z'), vec![vec![(b'a', b'z')]]);
+        assert_eq!(sequences('é', 'é'), vec![vec![(0xC3, 0xC3), (0xA9, 0xA9)]]);
+        assert_eq!(
+            sequences('\u{7F}', '\u{80}'),
+            vec![vec![(0x7F, 0x7F)], vec![(0xC2, 0xC2), (0x80, 0x80)]],
+        );
+
+        for (start, end) in [('\0', char::MAX), ('α', 'ω'), ('\u{FFF0}', '\u{10010}')] {
+            let sequences = sequences(start, end);
+
+            for c in (start..=end).step_by(7) {
+                let mut buffer = [0; 4];
+                let bytes = c.encode_utf8(&mut buffer).as_bytes();
+                let matching = sequences
+                    .iter()
+                    .filter(|sequence| {
+                        sequence.len() == bytes.len()
+                            && sequence
+                                .iter()
+                                .zip(bytes)
+                                .all(|((first, last), b)| (first..=last).contains(&b))
+                    })
+                    .count();
+
+                assert_eq!(matching, 1, "{c:?}");
+            }
+        }
+    }
+}
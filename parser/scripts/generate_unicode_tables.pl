@@ -0,0 +1,129 @@
+#!/usr/bin/env perl
+# Generates the Unicode tables in src/unicode_tables from the Unicode database shipped
+# with Perl. Run from the parser directory:
+#
+#     perl scripts/generate_unicode_tables.pl
+
+use strict;
+use warnings;
+use Unicode::UCD qw(all_casefolds prop_invlist prop_value_aliases prop_values);
+
+my $directory = 'src/unicode_tables';
+my $version = Unicode::UCD::UnicodeVersion();
+
+# Turns an inversion list into inclusive ranges of chars, leaving out the surrogates,
+# which are not chars in Rust.
+sub ranges {
+    my @list = prop_invlist(shift);
+    my @ranges;
+
+    for (my $i = 0; $i < @list; $i += 2) {
+        my $start = $list[$i];
+        my $end = $i + 1 < @list ? $list[$i + 1] - 1 : 0x10FFFF;
+
+        push @ranges, [$start, $end < 0xD800 ? $end : 0xD7FF] if $start < 0xD800;
+        push @ranges, [$start > 0xE000 ? $start : 0xE000, $end] if $end >= 0xE000;
+    }
+
+    return @ranges;
+}
+
+sub table {
+    my ($name, $property) = @_;
+    my $table = "pub(crate) const $name: &[(char, char)] = &[\n";
+
+    for my $range (ranges($property)) {
+        $table .= sprintf("    ('\\u{%X}', '\\u{%X}'),\n", @$range);
+    }
+
+    return "$table];\n";
+}
+
+# Names are matched loosely: case, spaces, `_` and `-` are ignored.
+sub normalize {
+    my $name = lc(shift);
+    $name =~ s/[ _-]//g;
+    return $name;
+}
+
+# Generates one table per value of the property, named after its long name, and a
+# `BY_NAME` index from every alias of the values, sorted for binary search.
+sub property_tables {
+    my $property = shift;
+    my (@tables, %by_name);
+
+    for my $value (sort(prop_values($property))) {
+        my @aliases = prop_value_aliases($property, $value);
+        @aliases = ($value) unless @aliases;
+
+        my $name = uc($aliases[1] // $aliases[0]);
+
+        push @tables, table($name, "$property=$value");
+
+        for my $alias (grep { /^\w+$/ } @aliases) {
+            $by_name{normalize($alias)} //= $name;
+        }
+    }
+
+    my $index = "pub(crate) const BY_NAME: &[(&str, &[(char, char)])] = &[\n";
+
+    for my $alias (sort(keys(%by_name))) {
+        $index .= "    (\"$alias\", $by_name{$alias}),\n";
+    }
+
+    return ("$index];\n", @tables);
+}
+
+# Generates the simple case folding as sorted pairs, mapping each char to every other char
+# that folds to the same char, so that `k` maps to `K` and KELVIN SIGN.
+sub case_folding_table {
+    my $folds = all_casefolds();
+    my %orbits;
+
+    for my $code (keys(%$folds)) {
+        my $simple = $folds->{$code}{simple};
+        next unless $simple;
+
+        my $target = hex($simple);
+        $orbits{$target}{$target} = 1;
+        $orbits{$target}{$code} = 1;
+    }
+
+    my @pairs;
+
+    for my $orbit (values(%orbits)) {
+        my @chars = keys(%$orbit);
+
+        for my $from (@chars) {
+            push @pairs, map { [$from, $_] } grep { $_ != $from } @chars;
+        }
+    }
+
+    my $table = "pub(crate) const CASE_FOLDING: &[(char, char)] = &[\n";
+
+    for my $pair (sort { $a->[0] <=> $b->[0] || $a->[1] <=> $b->[1] } @pairs) {
+        $table .= sprintf("    ('\\u{%X}', '\\u{%X}'),\n", @$pair);
+    }
+
+    return "$table];\n";
+}
+
+sub write_module {
+    my ($name, @tables) = @_;
+
+    open(my $file, '>', "$directory/$name.rs") or die "cannot write $name.rs: $!";
+    print $file "// Generated by scripts/generate_unicode_tables.pl from Unicode $version.\n";
+    print $file "// Do not edit by hand.\n";
+    print $file "\n", join("\n", @tables);
+    close($file);
+}
+
+write_module(
+    'perl',
+    table('PERL_DIGIT', 'Nd'),
+    table('PERL_SPACE', 'White_Space'),
+    table('PERL_WORD', 'Word'),
+);
+write_module('case_folding', case_folding_table());
+write_module('general_category', property_tables('gc'));
+write_module('script', property_tables('sc'));
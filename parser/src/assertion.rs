@@ -0,0 +1,109 @@
+use crate::unicode_tables::perl::PERL_WORD;
+use std::{cmp::Ordering, fmt};
+
+/// A zero-width assertion about the chars around the current position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Assertion {
+    /// `^` in multi-line mode.
+    StartLine,
+    /// `$` in multi-line mode.
+    EndLine,
+    /// `\A`, or `^` outside multi-line mode.
+    StartText,
+    /// `\z`, or `$` outside multi-line mode.
+    EndText,
+    /// `\b`
+    WordBoundary,
+    /// `\B`
+    NotWordBoundary,
+    /// `\b` outside Unicode mode, which sees only ASCII word chars.
+    WordBoundaryAscii,
+    /// `\B` outside Unicode mode, which sees only ASCII word chars.
+    NotWordBoundaryAscii,
+}
+
+impl Assertion {
+    /// Checks the assertion between `behind` and `ahead`, where `None` stands for the edge of the
+    /// text.
+    pub fn is_satisfied(&self, behind: Option<char>, ahead: Option<char>) -> bool {
+        let is_word = |c: Option<char>| c.is_some_and(is_word_char);
+        let is_ascii_word =
+            |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        match self {
+            Self::StartLine => matches!(behind, None | Some('\n')),
+            Self::EndLine => matches!(ahead, None | Some('\n')),
+            Self::StartText => behind.is_none(),
+            Self::EndText => ahead.is_none(),
+            Self::WordBoundary => is_word(behind) != is_word(ahead),
+            Self::NotWordBoundary => is_word(behind) == is_word(ahead),
+            Self::WordBoundaryAscii => is_ascii_word(behind) != is_ascii_word(ahead),
+            Self::NotWordBoundaryAscii => is_ascii_word(behind) == is_ascii_word(ahead),
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pattern = match self {
+            Self::StartLine => "(?m:^)",
+            Self::EndLine => "(?m:$)",
+            Self::StartText => r"\A",
+            Self::EndText => r"\z",
+            Self::WordBoundary => r"\b",
+            Self::NotWordBoundary => r"\B",
+            Self::WordBoundaryAscii => r"(?-u:\b)",
+            Self::NotWordBoundaryAscii => r"(?-u:\B)",
+        };
+
+        write!(f, "{pattern}")
+    }
+}
+
+/// Returns true if `c` is a Unicode word char, as matched by `\w`.
+pub fn is_word_char(c: char) -> bool {
+    PERL_WORD
+        .binary_search_by(|(start, end)| match (*start > c, *end < c) {
+            (true, _) => Ordering::Greater,
+            (_, true) => Ordering::Less,
+            _ => Ordering::Equal,
+        })
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{is_word_char, Assertion};
+
+    #[test]
+    fn test_is_satisfied() {
+        assert!(Assertion::StartLine.is_satisfied(Some('\n'), Some('a')));
+        assert!(!Assertion::StartText.is_satisfied(Some('\n'), Some('a')));
+        assert!(Assertion::EndLine.is_satisfied(Some('a'), None));
+        assert!(!Assertion::EndText.is_satisfied(Some('a'), Some('\n')));
+        assert!(Assertion::WordBoundary.is_satisfied(Some(' '), Some('é')));
+        assert!(Assertion::WordBoundary.is_satisfied(Some('_'), None));
+        assert!(!Assertion::WordBoundary.is_satisfied(None, None));
+        assert!(Assertion::NotWordBoundary.is_satisfied(Some('a'), Some('1')));
+        assert!(Assertion::WordBoundaryAscii.is_satisfied(Some('é'), Some('a')));
+        assert!(Assertion::NotWordBoundaryAscii.is_satisfied(Some(' '), Some('é')));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Assertion::StartLine.to_string(), "(?m:^)");
+        assert_eq!(Assertion::EndText.to_string(), r"\z");
+        assert_eq!(Assertion::NotWordBoundaryAscii.to_string(), r"(?-u:\B)");
+    }
+
+    #[test]
+    fn test_is_word_char() {
+        assert!(is_word_char('a'));
+        assert!(is_word_char('_'));
+        assert!(is_word_char('9'));
+        assert!(is_word_char('é'));
+        assert!(is_word_char('\u{0663}'));
+        assert!(!is_word_char(' '));
+        assert!(!is_word_char('-'));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::unicode_tables::{
+    self,
+    case_folding::CASE_FOLDING,
+    perl::{PERL_DIGIT, PERL_SPACE, PERL_WORD},
+};
+use std::{char::from_u32, fmt};
+
+/// A set of chars, stored as sorted, non-overlapping and non-adjacent ranges.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Class {
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    pub fn new(ranges: impl IntoIterator<Item = (char, char)>) -> Self {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|(start, end)| start <= end)
+            .collect::<Vec<_>>();
+        ranges.sort();
+
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some((_, last)) if start <= increment(*last).unwrap_or(char::MAX) => {
+                    *last = (*last).max(end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+
+        Self { ranges: merged }
+    }
+
+    /// Returns the class of all chars, or of all chars but `\n`.
+    pub fn any(newline: bool) -> Self {
+        if newline {
+            Self::new([('\0', char::MAX)])
+        } else {
+            Self::new([('\0', '\u{9}'), ('\u{B}', char::MAX)])
+        }
+    }
+
+    /// Returns the class of `\d`: the Unicode decimal digits, or `[0-9]` outside Unicode mode.
+    pub fn digit(unicode: bool) -> Self {
+        match unicode {
+            true => Self::new(PERL_DIGIT.iter().cloned()),
+            false => Self::new([('0', '9')]),
+        }
+    }
+
+    /// Returns the class of `\s`: the Unicode white space, or `[\t\n\v\f\r ]` outside
+    /// Unicode mode.
+    pub fn space(unicode: bool) -> Self {
+        match unicode {
+            true => Self::new(PERL_SPACE.iter().cloned()),
+            false => Self::new([('\t', '\r'), (' ', ' ')]),
+        }
+    }
+
+    /// Returns the class of `\w`: the Unicode word chars, or `[0-9A-Za-z_]` outside Unicode
+    /// mode.
+    pub fn word(unicode: bool) -> Self {
+        match unicode {
+            true => Self::new(PERL_WORD.iter().cloned()),
+            false => Self::new([('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+        }
+    }
+
+    /// Returns the class of a Unicode general category or script, as in `\p{Greek}`.
+    pub fn property(name: &str) -> Option<Self> {
+        unicode_tables::property(name).map(|table| Self::new(table.iter().cloned()))
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let index = self.ranges.partition_point(|(_, end)| *end < c);
+        self.ranges.get(index).is_some_and(|(start, _)| *start <= c)
+    }
+
+    /// Returns the class closed under simple case folding, so that `k` also matches `K` and
+    /// KELVIN SIGN.
+    pub fn case_fold(&self) -> Self {
+        let mut ranges = self.ranges.clone();
+
+        for (start, end) in &self.ranges {
+            let from = CASE_FOLDING.partition_point(|(c, _)| c < start);
+            let folded = CASE_FOLDING[from..]
+                .iter()
+                .take_while(|(c, _)| c <= end)
+                .map(|(_, folded)| (*folded, *folded));
+
+            ranges.extend(folded);
+        }
+
+        Self::new(ranges)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.ranges.iter().chain(&other.ranges).cloned())
+    }
+
+    pub fn negate(&self) -> Self {
+        let mut ranges = Vec::new();
+        let mut next = Some('\0');
+
+        for (start, end) in &self.ranges {
+            if let (Some(next), Some(previous)) = (next, decrement(*start)) {
+                if next <= previous {
+                    ranges.push((next, previous));
+                }
+            }
+
+            next = increment(*end);
+        }
+
+        if let Some(next) = next {
+            ranges.push((next, char::MAX));
+        }
+
+        Self { ranges }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+
+        for (start, end) in &self.ranges {
+            write_class_char(f, *start)?;
+
+            if start != end {
+                if increment(*start) != Some(*end) {
+                    write!(f, "-")?;
+                }
+
+                write_class_char(f, *end)?;
+            }
+        }
+
+        write!(f, "]")
+    }
+}
+
+// Writes a char as it would appear in a class, escaping the chars that are special there
+// and those that are not printable.
+fn write_class_char(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '[' | ']' | '-' | '^' | '\\' => write!(f, "\\{c}"),
+        '\'' | '"' => write!(f, "{c}"),
+        c => write!(f, "{}", c.escape_debug()),
+    }
+}
+
+impl From<char> for Class {
+    fn from(c: char) -> Self {
+        Self {
+            ranges: vec![(c, c)],
+        }
+    }
+}
+
+pub fn increment(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => from_u32(c as u32 + 1),
+    }
+}
+
+pub fn decrement(c: char) -> Option<char> {
+    match c {
+        '\0' => None,
+        '\u{E000}' => Some('\u{D7FF}'),
+        c => from_u32(c as u32 - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Class;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(
+            Class::new([('x', 'z'), ('a', 'c'), ('b', 'e'), ('f', 'f'), ('q', 'p')]).ranges(),
+            &[('a', 'f'), ('x', 'z')],
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        let class = Class::new([('a', 'c'), ('x', 'z')]);
+
+        assert!(class.contains('a'));
+        assert!(class.contains('b'));
+        assert!(class.contains('z'));
+
+        assert!(!class.contains('d'));
+        assert!(!class.contains('A'));
+    }
+
+    #[test]
+    fn test_perl() {
+        assert!(Class::digit(true).contains('\u{0663}'));
+        assert!(!Class::digit(false).contains('\u{0663}'));
+        assert!(Class::space(true).contains('\u{3000}'));
+        assert!(Class::space(false).contains('\u{B}'));
+        assert!(!Class::space(false).contains('\u{3000}'));
+        assert!(Class::word(true).contains('é'));
+        assert!(!Class::word(false).contains('é'));
+        assert_eq!(
+            Class::word(false).ranges(),
+            &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        );
+    }
+
+    #[test]
+    fn test_property() {
+        let greek = Class::property("Greek").unwrap();
+
+        assert!(greek.contains('α'));
+        assert!(!greek.contains('a'));
+        assert_eq!(Class::property("sc=Grek"), Some(greek.clone()));
+        assert_eq!(Class::property("script = greek"), Some(greek));
+
+        assert!(Class::property("Lu").unwrap().contains('Σ'));
+        assert!(!Class::property("Lu").unwrap().contains('σ'));
+        assert!(Class::property("L").unwrap().contains('σ'));
+        assert!(Class::property("Han").unwrap().contains('漢'));
+        assert!(Class::property("Nd").unwrap().contains('\u{663}'));
+        assert!(Class::property("Decimal_Number").unwrap().contains('7'));
+        assert_eq!(Class::property("gc=Greek"), None);
+        assert_eq!(Class::property("Klingon"), None);
+    }
+
+    #[test]
+    fn test_case_fold() {
+        assert_eq!(
+            Class::from('k').case_fold().ranges(),
+            &[('K', 'K'), ('k', 'k'), ('\u{212A}', '\u{212A}')],
+        );
+        assert_eq!(
+            Class::new([('a', 'c'), ('1', '3')]).case_fold().ranges(),
+            &[('1', '3'), ('A', 'C'), ('a', 'c')],
+        );
+        assert_eq!(
+            Class::from('Σ').case_fold().ranges(),
+            &[('Σ', 'Σ'), ('ς', 'σ')],
+        );
+        assert_eq!(Class::from('-').case_fold(), Class::from('-'));
+    }
+
+    #[test]
+    fn test_negate() {
+        assert_eq!(
+            Class::new([('a', 'c'), ('x', 'z')]).negate().ranges(),
+            &[('\0', '`'), ('d', 'w'), ('{', char::MAX)],
+        );
+        assert_eq!(
+            Class::new([('\0', '\u{D7FF}')]).negate().ranges(),
+            &[('\u{E000}', char::MAX)],
+        );
+        assert_eq!(
+            Class::new([('\0', char::MAX)]).negate().ranges(),
+            &[] as &[(char, char)],
+        );
+        assert_eq!(Class::new([]).negate().negate(), Class::new([]),);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Class::new([('a', 'z'), ('_', '_')]).to_string(), "[_a-z]");
+        assert_eq!(Class::new([('0', '1'), ('-', '-')]).to_string(), r"[\-01]");
+        assert_eq!(Class::new([('\n', '\n'), ('é', 'é')]).to_string(), r"[\né]");
+        assert_eq!(Class::any(false).to_string(), r"[\0-\t\u{b}-\u{10ffff}]");
+        assert_eq!(Class::new([]).to_string(), "[]");
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+/// An error in a pattern, found at `offset` bytes from its start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    offset: usize,
+    kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `(` without a matching `)`.
+    UnclosedGroup,
+    /// A `)` without a matching `(`.
+    UnopenedGroup,
+    /// A `\` at the end of the pattern.
+    DanglingEscape,
+    /// A repetition operator with nothing to repeat, as in `+a` or `a**`.
+    RepetitionMissing,
+    /// A counted repetition whose minimum is above its maximum, as in `a{5,3}`.
+    InvalidRepetitionRange,
+    /// A counted repetition whose count is above the repeat limit.
+    RepetitionTooLarge { limit: usize },
+    /// A group nested more deeply than the nest limit.
+    NestingTooDeep { limit: usize },
+    /// A `[]` or `[^]`.
+    EmptyClass,
+    /// A `[` without a matching `]`.
+    UnclosedClass,
+    /// A class range whose start is after its end, as in `[z-a]`.
+    InvalidClassRange,
+    /// A `\p` or `\P` not followed by a name in braces or a single letter.
+    InvalidProperty,
+    /// A `\p{...}` naming neither a general category nor a script.
+    UnknownProperty,
+    /// An inline flag other than `i`, `m`, `s` and `u`, as in `(?x)`.
+    UnknownFlag,
+    /// A group name that is empty, does not start with a letter or `_`, or is not closed by
+    /// `>`, as in `(?P<1>a)`.
+    InvalidGroupName,
+}
+
+impl ParseError {
+    pub fn new(offset: usize, kind: ParseErrorKind) -> Self {
+        Self { offset, kind }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    pub fn expected(&self) -> String {
+        match self.kind {
+            ParseErrorKind::UnclosedGroup => "`)`".to_string(),
+            ParseErrorKind::UnopenedGroup => "the end of the pattern".to_string(),
+            ParseErrorKind::DanglingEscape => "a char after `\\`".to_string(),
+            ParseErrorKind::RepetitionMissing => "an expression to repeat".to_string(),
+            ParseErrorKind::InvalidRepetitionRange => {
+                "a minimum no larger than the maximum".to_string()
+            }
+            ParseErrorKind::RepetitionTooLarge { limit } => format!("a count of at most {limit}"),
+            ParseErrorKind::NestingTooDeep { limit } => {
+                format!("groups nested at most {limit} deep")
+            }
+            ParseErrorKind::EmptyClass => "a char or a range".to_string(),
+            ParseErrorKind::UnclosedClass => "`]`".to_string(),
+            ParseErrorKind::InvalidClassRange => {
+                "a range whose start is not after its end".to_string()
+            }
+            ParseErrorKind::InvalidProperty => "a property name such as `{Greek}`".to_string(),
+            ParseErrorKind::UnknownProperty => "a Unicode general category or script".to_string(),
+            ParseErrorKind::UnknownFlag => "one of the flags `i`, `m`, `s` and `u`".to_string(),
+            ParseErrorKind::InvalidGroupName => "a group name such as `<word>`".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::UnclosedGroup => "unclosed group",
+            Self::UnopenedGroup => "unopened group",
+            Self::DanglingEscape => "dangling escape",
+            Self::RepetitionMissing => "repetition operator missing an expression",
+            Self::InvalidRepetitionRange => "invalid repetition range",
+            Self::RepetitionTooLarge { .. } => "repetition count too large",
+            Self::NestingTooDeep { .. } => "nesting too deep",
+            Self::EmptyClass => "empty class",
+            Self::UnclosedClass => "unclosed class",
+            Self::InvalidClassRange => "invalid class range",
+            Self::InvalidProperty => "invalid Unicode property",
+            Self::UnknownProperty => "unknown Unicode property",
+            Self::UnknownFlag => "unknown flag",
+            Self::InvalidGroupName => "invalid group name",
+        };
+
+        f.write_str(description)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {}, expected {}",
+            self.kind,
+            self.offset,
+            self.expected()
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// The error type of the nom parsers. Recoverable errors carry no kind; the parsers
+// fail with a kind wherever the pattern is known to be invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Error<'a> {
+    pub(crate) input: &'a str,
+    pub(crate) kind: Option<ParseErrorKind>,
+}
+
+impl<'a> Error<'a> {
+    pub(crate) fn failure(input: &'a str, kind: ParseErrorKind) -> nom::Err<Self> {
+        nom::Err::Failure(Self {
+            input,
+            kind: Some(kind),
+        })
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a str> for Error<'a> {
+    fn from_error_kind(input: &'a str, _: nom::error::ErrorKind) -> Self {
+        Self { input, kind: None }
+    }
+
+    fn append(_: &'a str, _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
@@ -1,12 +1,29 @@
-use anyhow::{bail, Result};
+use error::Error;
 use nom::{
     branch::alt,
-    character::complete::{anychar, char, none_of},
-    combinator::{eof, map, opt},
-    sequence::delimited,
-    IResult, Parser,
+    bytes::complete::{is_not, tag},
+    character::complete::{anychar, char, digit1, none_of, satisfy},
+    combinator::{map, opt, recognize, value},
+    multi::many0,
+    sequence::{delimited, pair, preceded, terminated},
+    Parser,
 };
 
+pub use assertion::{is_word_char, Assertion};
+pub use class::{decrement, increment, Class};
+pub use error::{ParseError, ParseErrorKind};
+pub use regex::{Captures, Match, Regex, RegexExt, Replacer, Split, SplitN};
+
+mod assertion;
+mod class;
+mod error;
+mod regex;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod unicode_tables;
+
+type IResult<'a, O> = nom::IResult<&'a str, O, Error<'a>>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     Empty,
@@ -14,69 +31,615 @@ pub enum Node {
     Concat(Box<Node>, Box<Node>),
     Union(Box<Node>, Box<Node>),
     Star(Box<Node>),
+    /// A capturing group with its index, counted from 1, and its name if it has one.
+    Group(usize, Option<String>, Box<Node>),
+    Class(Class),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+    Any {
+        newline: bool,
+    },
+    Assert(Assertion),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Flags {
+    /// Lets `.` match `\n`.
+    pub dot_matches_newline: bool,
+    /// Lets `^` and `$` match at the start and end of each line.
+    pub multi_line: bool,
+    /// Makes `\d`, `\s`, `\w` and `\b` Unicode-aware rather than ASCII-only.
+    pub unicode: bool,
+    /// Matches letters regardless of their case, using simple Unicode case folding.
+    pub case_insensitive: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Self {
+            dot_matches_newline: false,
+            multi_line: false,
+            unicode: true,
+            case_insensitive: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    /// The largest count allowed in a bounded repetition such as `a{n,m}`.
+    pub repeat_limit: usize,
+    /// The deepest nesting of groups allowed.
+    pub nest_limit: usize,
+    pub flags: Flags,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            repeat_limit: 1000,
+            nest_limit: 100,
+            flags: Flags::default(),
+        }
+    }
 }
 
 impl Node {
     pub fn is_star(&self) -> bool {
         matches!(self, Self::Star(_))
     }
+
+    pub fn groups(&self) -> usize {
+        let mut groups = 0;
+        self.walk(|node| {
+            if let Self::Group(..) = node {
+                groups += 1;
+            }
+        });
+        groups
+    }
+
+    /// Returns the names of the capturing groups by index, with `None` for group 0 and for
+    /// the groups without a name.
+    pub fn group_names(&self) -> Vec<Option<String>> {
+        let mut names = vec![None];
+        self.walk(|node| {
+            if let Self::Group(_, name, _) = node {
+                names.push(name.clone());
+            }
+        });
+        names
+    }
+
+    /// Returns the items of a chain of concatenations, such as `a`, `b` and `c` for `abc`.
+    pub fn concat_items(&self) -> Vec<&Self> {
+        let mut items = Vec::new();
+        let mut node = self;
+
+        while let Self::Concat(left, right) = node {
+            items.push(&**left);
+            node = right;
+        }
+
+        items.push(node);
+        items
+    }
+
+    /// Returns the alternatives of a chain of unions, such as `a`, `b` and `c` for `a|b|c`.
+    pub fn alternatives(&self) -> Vec<&Self> {
+        let mut alternatives = Vec::new();
+        let mut node = self;
+
+        while let Self::Union(left, right) = node {
+            alternatives.push(&**left);
+            node = right;
+        }
+
+        alternatives.push(node);
+        alternatives
+    }
+
+    fn index_groups(&mut self, next: &mut usize) {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            match node {
+                Self::Empty
+                | Self::Char(_)
+                | Self::Class(_)
+                | Self::Any { .. }
+                | Self::Assert(_) => {}
+                Self::Concat(left, right) | Self::Union(left, right) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+                Self::Star(node) | Self::Repeat { node, .. } => stack.push(node),
+                Self::Group(index, _, node) => {
+                    *index = *next;
+                    *next += 1;
+                    stack.push(node);
+                }
+            }
+        }
+    }
+
+    /// Visits the node and its descendants in pre-order, from left to right. Long
+    /// concatenations and alternations nest deeply, so the walk does not recurse.
+    fn walk<'a>(&'a self, mut visit: impl FnMut(&'a Self)) {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            visit(node);
+
+            match node {
+                Self::Empty
+                | Self::Char(_)
+                | Self::Class(_)
+                | Self::Any { .. }
+                | Self::Assert(_) => {}
+                Self::Concat(left, right) | Self::Union(left, right) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+                Self::Star(node) | Self::Repeat { node, .. } | Self::Group(_, _, node) => {
+                    stack.push(node)
+                }
+            }
+        }
+    }
+}
+
+// Long concatenations and alternations nest as deeply as they are long, so the children
+// are moved onto a stack and dropped from there rather than recursively.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+
+        while let Some(mut node) = stack.pop() {
+            node.take_children(&mut stack);
+        }
+    }
 }
 
-pub fn parse(input: &str) -> Result<Node> {
-    match expression(input) {
-        Ok((_, node)) => Ok(node),
-        Err(_) => bail!("failed to parse"),
+impl Node {
+    fn take_children(&mut self, stack: &mut Vec<Node>) {
+        match self {
+            Self::Empty | Self::Char(_) | Self::Class(_) | Self::Any { .. } | Self::Assert(_) => {}
+            Self::Concat(left, right) | Self::Union(left, right) => {
+                stack.push(std::mem::replace(left, Self::Empty));
+                stack.push(std::mem::replace(right, Self::Empty));
+            }
+            Self::Star(node) | Self::Repeat { node, .. } | Self::Group(_, _, node) => {
+                stack.push(std::mem::replace(node, Self::Empty));
+            }
+        }
     }
 }
 
-fn expression(input: &str) -> IResult<&str, Node> {
-    map((sub_expression, eof), |(node, _)| node).parse(input)
+pub fn parse(input: &str) -> Result<Node, ParseError> {
+    parse_with(input, &Config::default())
 }
 
-fn sub_expression(input: &str) -> IResult<&str, Node> {
-    alt((
-        map((sequence, char('|'), sub_expression), |(left, _, right)| {
-            Node::Union(Box::new(left), Box::new(right))
-        }),
-        sequence,
-    ))
+pub fn parse_with(input: &str, config: &Config) -> Result<Node, ParseError> {
+    match expression(input, *config) {
+        Ok((_, mut node)) => {
+            node.index_groups(&mut 1);
+            Ok(node)
+        }
+        Err(nom::Err::Error(error) | nom::Err::Failure(error)) => {
+            let offset = input.len() - error.input.len();
+            let kind = error.kind.unwrap_or_else(|| unexpected(error.input));
+            Err(ParseError::new(offset, kind))
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!("the parsers are complete"),
+    }
+}
+
+// Classifies the input that none of the parsers could consume.
+fn unexpected(input: &str) -> ParseErrorKind {
+    match input.chars().next() {
+        Some(')') => ParseErrorKind::UnopenedGroup,
+        Some('\\') => ParseErrorKind::DanglingEscape,
+        _ => ParseErrorKind::RepetitionMissing,
+    }
+}
+
+fn expression(input: &str, config: Config) -> IResult<'_, Node> {
+    let (rest, node) = sub_expression(input, config, 0)?;
+
+    match rest.is_empty() {
+        true => Ok((rest, node)),
+        false => Err(Error::failure(rest, unexpected(rest))),
+    }
+}
+
+// `depth` is the number of groups enclosing the input, which is checked against the nest
+// limit so that deeply nested patterns fail instead of overflowing the stack.
+fn sub_expression(mut input: &str, mut config: Config, depth: usize) -> IResult<'_, Node> {
+    let mut alternatives = Vec::new();
+
+    loop {
+        let (rest, (node, next)) = sequence(input, config, depth)?;
+        alternatives.push(node);
+        config = next;
+
+        match char::<_, Error>('|').parse(rest) {
+            Ok((rest, _)) => input = rest,
+            Err(_) => {
+                input = rest;
+                break;
+            }
+        }
+    }
+
+    let node = alternatives
+        .into_iter()
+        .rev()
+        .reduce(|right, left| Node::Union(Box::new(left), Box::new(right)))
+        .unwrap_or(Node::Empty);
+
+    Ok((input, node))
+}
+
+// Parses a sequence of repeats, applying inline flags such as `(?i)` to the rest of the
+// enclosing group, including its later alternatives, whose config is returned.
+fn sequence(mut input: &str, mut config: Config, depth: usize) -> IResult<'_, (Node, Config)> {
+    let mut nodes = Vec::new();
+
+    loop {
+        let current = config.flags;
+
+        match delimited(char('('), |input| flags(input, current), char(')')).parse(input) {
+            Ok((rest, flags)) => {
+                config.flags = flags;
+                input = rest;
+                continue;
+            }
+            Err(nom::Err::Error(_)) => (),
+            Err(error) => return Err(error),
+        }
+
+        match repeat(input, config, depth) {
+            Ok((rest, node)) => {
+                nodes.push(node);
+                input = rest;
+            }
+            Err(nom::Err::Error(_)) => break,
+            Err(error) => return Err(error),
+        }
+    }
+
+    let node = nodes
+        .into_iter()
+        .rev()
+        .reduce(|right, left| Node::Concat(Box::new(left), Box::new(right)))
+        .unwrap_or(Node::Empty);
+
+    Ok((input, (node, config)))
+}
+
+fn repeat(input: &str, config: Config, depth: usize) -> IResult<'_, Node> {
+    let (input, node) = factor(input, config, depth)?;
+    let (rest, quantifier) = opt(quantifier).parse(input)?;
+
+    let node = match quantifier {
+        Some((min, max, _)) if max.unwrap_or(min) > config.repeat_limit => {
+            let limit = config.repeat_limit;
+            return Err(Error::failure(
+                input,
+                ParseErrorKind::RepetitionTooLarge { limit },
+            ));
+        }
+        Some((0, None, true)) => Node::Star(Box::new(node)),
+        Some((min, max, greedy)) => Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        },
+        None => node,
+    };
+
+    Ok((rest, node))
+}
+
+fn quantifier(input: &str) -> IResult<'_, (usize, Option<usize>, bool)> {
+    map(
+        (
+            alt((
+                value((0, None), char('*')),
+                value((1, None), char('+')),
+                value((0, Some(1)), char('?')),
+                counter,
+            )),
+            opt(char('?')),
+        ),
+        |((min, max), lazy)| (min, max, lazy.is_none()),
+    )
     .parse(input)
 }
 
-fn sequence(input: &str) -> IResult<&str, Node> {
-    map(opt(sub_sequence), |node| node.unwrap_or(Node::Empty)).parse(input)
+fn counter(input: &str) -> IResult<'_, (usize, Option<usize>)> {
+    let (rest, (min, max)) = delimited(
+        char('{'),
+        (number, opt(preceded(char(','), opt(number)))),
+        char('}'),
+    )
+    .parse(input)?;
+
+    match max {
+        None => Ok((rest, (min, Some(min)))),
+        Some(None) => Ok((rest, (min, None))),
+        Some(Some(max)) if min <= max => Ok((rest, (min, Some(max)))),
+        Some(Some(_)) => Err(Error::failure(
+            input,
+            ParseErrorKind::InvalidRepetitionRange,
+        )),
+    }
+}
+
+// A count too large for `usize` is saturated, so that it is reported against the
+// repeat limit.
+fn number(input: &str) -> IResult<'_, usize> {
+    map(digit1, |digits: &str| digits.parse().unwrap_or(usize::MAX)).parse(input)
 }
 
-fn sub_sequence(input: &str) -> IResult<&str, Node> {
+fn factor(input: &str, config: Config, depth: usize) -> IResult<'_, Node> {
     alt((
-        map((star, sub_sequence), |(left, right)| {
-            Node::Concat(Box::new(left), Box::new(right))
-        }),
-        star,
+        |input| group(input, config, depth),
+        |input| class(input, config.flags),
+        value(
+            Node::Any {
+                newline: config.flags.dot_matches_newline,
+            },
+            char('.'),
+        ),
+        map(|input| assertion(input, config.flags), Node::Assert),
+        map(|input| escape_class(input, config.flags), Node::Class),
+        map(
+            alt((none_of("|*+?.()[^$\\"), preceded(char('\\'), anychar))),
+            |c| literal(c, config.flags),
+        ),
     ))
     .parse(input)
 }
 
-fn star(input: &str) -> IResult<&str, Node> {
+fn literal(c: char, flags: Flags) -> Node {
+    if !flags.case_insensitive {
+        return Node::Char(c);
+    }
+
+    match Class::from(c).case_fold() {
+        class if class.ranges() == [(c, c)] => Node::Char(c),
+        class => Node::Class(class),
+    }
+}
+
+// Parses a capturing group, which may be named as in `(?P<name>a)` or `(?<name>a)`, or a
+// non-capturing group such as `(?:a)` or `(?i:a)` whose flags apply only inside it.
+fn group(input: &str, config: Config, depth: usize) -> IResult<'_, Node> {
+    let (rest, _) = char('(').parse(input)?;
+
+    if depth >= config.nest_limit {
+        let limit = config.nest_limit;
+        return Err(Error::failure(
+            input,
+            ParseErrorKind::NestingTooDeep { limit },
+        ));
+    }
+    let (rest, name) = opt(group_name).parse(rest)?;
+    let (rest, flags) = match name {
+        Some(_) => (rest, None),
+        None => opt(|input| flags(input, config.flags)).parse(rest)?,
+    };
+
+    let rest = match flags.map(|_| char::<_, Error>(':').parse(rest)) {
+        Some(Ok((rest, _))) => rest,
+        None => rest,
+        Some(Err(_)) if rest.is_empty() => {
+            return Err(Error::failure(input, ParseErrorKind::UnclosedGroup))
+        }
+        Some(Err(_)) => return Err(Error::failure(rest, unexpected(rest))),
+    };
+
+    let inner = Config {
+        flags: flags.unwrap_or(config.flags),
+        ..config
+    };
+    let (rest, node) = sub_expression(rest, inner, depth + 1)?;
+
+    match char::<_, Error>(')').parse(rest) {
+        Ok((rest, _)) if flags.is_some() => Ok((rest, node)),
+        Ok((rest, _)) => Ok((rest, Node::Group(0, name, Box::new(node)))),
+        Err(_) if rest.is_empty() => Err(Error::failure(input, ParseErrorKind::UnclosedGroup)),
+        Err(_) => Err(Error::failure(rest, unexpected(rest))),
+    }
+}
+
+// Parses the `?P<name>` or `?<name>` of a named group after the `(`. A name starts with a
+// letter or `_`, followed by letters, digits and `_`.
+fn group_name(input: &str) -> IResult<'_, String> {
+    let (rest, _) = alt((tag("?P<"), tag("?<"))).parse(input)?;
+    let name = recognize(pair(
+        satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+        many0(satisfy(|c| c.is_ascii_alphanumeric() || c == '_')),
+    ));
+
+    match terminated(name, char::<_, Error>('>')).parse(rest) {
+        Ok((rest, name)) => Ok((rest, name.to_string())),
+        Err(_) => Err(Error::failure(rest, ParseErrorKind::InvalidGroupName)),
+    }
+}
+
+// Parses the flags of `(?i)`, `(?-i)` or `(?i:...)` after the `(`, returning `flags` updated.
+// The flags are `i` for case-insensitive, `m` for multi-line, `s` for `.` matching `\n` and `u`
+// for Unicode, and those after a `-` are cleared. `(?)` is left to fail as a repetition.
+fn flags(input: &str, mut flags: Flags) -> IResult<'_, Flags> {
+    let (mut rest, _) = char('?').parse(input)?;
+    let mut enable = true;
+
+    if rest.starts_with(')') || rest.starts_with("P<") || rest.starts_with('<') {
+        return Err(nom::Err::Error(Error { input, kind: None }));
+    }
+
+    while let Some(c) = rest.chars().next() {
+        let flag = match c {
+            ':' | ')' => break,
+            '-' if enable => {
+                enable = false;
+                rest = &rest[1..];
+                continue;
+            }
+            'i' => &mut flags.case_insensitive,
+            'm' => &mut flags.multi_line,
+            's' => &mut flags.dot_matches_newline,
+            'u' => &mut flags.unicode,
+            _ => return Err(Error::failure(rest, ParseErrorKind::UnknownFlag)),
+        };
+
+        *flag = enable;
+        rest = &rest[1..];
+    }
+
+    Ok((rest, flags))
+}
+
+fn assertion(input: &str, flags: Flags) -> IResult<'_, Assertion> {
+    let (start, end) = match flags.multi_line {
+        true => (Assertion::StartLine, Assertion::EndLine),
+        false => (Assertion::StartText, Assertion::EndText),
+    };
+    let (word_boundary, not_word_boundary) = match flags.unicode {
+        true => (Assertion::WordBoundary, Assertion::NotWordBoundary),
+        false => (
+            Assertion::WordBoundaryAscii,
+            Assertion::NotWordBoundaryAscii,
+        ),
+    };
+
     alt((
-        map((factor, char('*')), |(node, _)| Node::Star(Box::new(node))),
-        factor,
+        value(start, char('^')),
+        value(end, char('$')),
+        preceded(
+            char('\\'),
+            alt((
+                value(Assertion::StartText, char('A')),
+                value(Assertion::EndText, char('z')),
+                value(word_boundary, char('b')),
+                value(not_word_boundary, char('B')),
+            )),
+        ),
     ))
     .parse(input)
 }
 
-fn factor(input: &str) -> IResult<&str, Node> {
+fn escape_class(input: &str, flags: Flags) -> IResult<'_, Class> {
     alt((
-        map(delimited(char('('), sub_expression, char(')')), |node| node),
-        map(none_of("|*()\\"), Node::Char),
-        map((char('\\'), anychar), |(_, c)| Node::Char(c)),
+        |input| perl_class(input, flags),
+        |input| property_class(input, flags),
     ))
     .parse(input)
 }
 
+fn perl_class(input: &str, flags: Flags) -> IResult<'_, Class> {
+    preceded(
+        char('\\'),
+        alt((
+            map(char('d'), |_| Class::digit(flags.unicode)),
+            map(char('D'), |_| Class::digit(flags.unicode).negate()),
+            map(char('s'), |_| Class::space(flags.unicode)),
+            map(char('S'), |_| Class::space(flags.unicode).negate()),
+            map(char('w'), |_| Class::word(flags.unicode)),
+            map(char('W'), |_| Class::word(flags.unicode).negate()),
+        )),
+    )
+    .parse(input)
+}
+
+fn property_class(input: &str, flags: Flags) -> IResult<'_, Class> {
+    let (rest, negated) = preceded(
+        char('\\'),
+        alt((value(false, char('p')), value(true, char('P')))),
+    )
+    .parse(input)?;
+
+    let (rest, name) = alt((
+        delimited(char::<_, Error>('{'), is_not("}"), char('}')),
+        recognize(satisfy(|c| c.is_ascii_alphabetic())),
+    ))
+    .parse(rest)
+    .map_err(|_| Error::failure(input, ParseErrorKind::InvalidProperty))?;
+
+    match Class::property(name).map(|class| fold(class, flags)) {
+        Some(class) if negated => Ok((rest, class.negate())),
+        Some(class) => Ok((rest, class)),
+        None => Err(Error::failure(input, ParseErrorKind::UnknownProperty)),
+    }
+}
+
+fn class(input: &str, flags: Flags) -> IResult<'_, Node> {
+    let (rest, (_, negated)) = (char('['), opt(char('^'))).parse(input)?;
+
+    if rest.starts_with(']') {
+        return Err(Error::failure(input, ParseErrorKind::EmptyClass));
+    }
+
+    let (rest, items) = many0(|input| class_item(input, flags)).parse(rest)?;
+
+    let rest = match char::<_, Error>(']').parse(rest) {
+        Ok((rest, _)) => rest,
+        Err(_) if rest.starts_with('\\') => {
+            return Err(Error::failure(rest, ParseErrorKind::DanglingEscape));
+        }
+        Err(_) => return Err(Error::failure(input, ParseErrorKind::UnclosedClass)),
+    };
+
+    let class = fold(
+        Class::new(items.iter().flat_map(|item| item.ranges().iter().cloned())),
+        flags,
+    );
+
+    match negated {
+        Some(_) => Ok((rest, Node::Class(class.negate()))),
+        None => Ok((rest, Node::Class(class))),
+    }
+}
+
+// Classes are folded before they are negated, so that `(?i)[^k]` matches neither `k` nor `K`.
+fn fold(class: Class, flags: Flags) -> Class {
+    match flags.case_insensitive {
+        true => class.case_fold(),
+        false => class,
+    }
+}
+
+fn class_item(input: &str, flags: Flags) -> IResult<'_, Class> {
+    match escape_class(input, flags) {
+        Err(nom::Err::Error(_)) => (),
+        result => return result,
+    }
+
+    let (rest, start) = class_char(input)?;
+
+    match (char('-'), class_char).parse(rest) {
+        Ok((rest, (_, end))) if start <= end => Ok((rest, Class::new([(start, end)]))),
+        Ok(_) => Err(Error::failure(input, ParseErrorKind::InvalidClassRange)),
+        Err(_) => Ok((rest, Class::from(start))),
+    }
+}
+
+fn class_char(input: &str) -> IResult<'_, char> {
+    alt((none_of("]\\"), preceded(char('\\'), anychar))).parse(input)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{parse, Node};
+    use crate::{parse, parse_with, Assertion, Class, Config, Flags, Node, ParseErrorKind};
 
     #[test]
     fn test_success() {
@@ -84,22 +647,369 @@ mod tests {
             parse(r"\a|(bc)*").unwrap(),
             Node::Union(
                 Box::new(Node::Char('a')),
-                Box::new(Node::Star(Box::new(Node::Concat(
-                    Box::new(Node::Char('b')),
-                    Box::new(Node::Char('c'))
+                Box::new(Node::Star(Box::new(Node::Group(
+                    1,
+                    None,
+                    Box::new(Node::Concat(
+                        Box::new(Node::Char('b')),
+                        Box::new(Node::Char('c'))
+                    ))
                 ))))
             ),
         );
 
+        assert_eq!(
+            parse(r"((a)|b)(c)").unwrap(),
+            Node::Concat(
+                Box::new(Node::Group(
+                    1,
+                    None,
+                    Box::new(Node::Union(
+                        Box::new(Node::Group(2, None, Box::new(Node::Char('a')))),
+                        Box::new(Node::Char('b')),
+                    )),
+                )),
+                Box::new(Node::Group(3, None, Box::new(Node::Char('c')))),
+            ),
+        );
+
         assert_eq!(
             parse(r"a|").unwrap(),
             Node::Union(Box::new(Node::Char('a')), Box::new(Node::Empty)),
         );
     }
 
+    #[test]
+    fn test_named_group() {
+        let node = parse(r"(?P<year>\d+)-(\d+)(?<day_2>\d+)").unwrap();
+
+        assert_eq!(node.groups(), 3);
+        assert_eq!(
+            node.group_names(),
+            vec![None, Some("year".into()), None, Some("day_2".into())],
+        );
+        assert_eq!(
+            parse(r"(?P<x>a|b)").unwrap(),
+            Node::Group(
+                1,
+                Some("x".into()),
+                Box::new(Node::Union(
+                    Box::new(Node::Char('a')),
+                    Box::new(Node::Char('b')),
+                )),
+            ),
+        );
+        assert_eq!(parse("a").unwrap().group_names(), vec![None]);
+    }
+
+    #[test]
+    fn test_class() {
+        assert_eq!(
+            parse(r"[a-cx]").unwrap(),
+            Node::Class(Class::new([('a', 'c'), ('x', 'x')])),
+        );
+
+        assert_eq!(
+            parse(r"[^\]a-]").unwrap(),
+            Node::Class(Class::new([('-', '-'), (']', ']'), ('a', 'a')]).negate()),
+        );
+
+        assert_eq!(
+            parse(r"a[-]").unwrap(),
+            Node::Concat(
+                Box::new(Node::Char('a')),
+                Box::new(Node::Class(Class::from('-'))),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_perl_class() {
+        let ascii = Config {
+            flags: Flags {
+                unicode: false,
+                ..Flags::default()
+            },
+            ..Config::default()
+        };
+
+        assert_eq!(parse(r"\d").unwrap(), Node::Class(Class::digit(true)));
+        assert_eq!(
+            parse(r"\W").unwrap(),
+            Node::Class(Class::word(true).negate()),
+        );
+        assert_eq!(
+            parse_with(r"\s", &ascii).unwrap(),
+            Node::Class(Class::space(false)),
+        );
+        assert_eq!(
+            parse_with(r"[\d_-]", &ascii).unwrap(),
+            Node::Class(Class::new([('-', '-'), ('0', '9'), ('_', '_')])),
+        );
+        assert_eq!(
+            parse_with(r"[^\D]", &ascii).unwrap(),
+            Node::Class(Class::digit(false)),
+        );
+        assert_eq!(
+            parse_with(r"\b", &ascii).unwrap(),
+            Node::Assert(Assertion::WordBoundaryAscii),
+        );
+    }
+
+    #[test]
+    fn test_property_class() {
+        let property = |name| Class::property(name).unwrap();
+
+        assert_eq!(parse(r"\p{Greek}").unwrap(), Node::Class(property("Greek")),);
+        assert_eq!(parse(r"\PL").unwrap(), Node::Class(property("L").negate()));
+        assert_eq!(
+            parse(r"[\p{Lu}\d]").unwrap(),
+            Node::Class(property("Lu").union(&Class::digit(true))),
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let concat = |left, right| Node::Concat(Box::new(left), Box::new(right));
+        let folded = |c| Node::Class(Class::from(c).case_fold());
+        let case_insensitive = Config {
+            flags: Flags {
+                case_insensitive: true,
+                ..Flags::default()
+            },
+            ..Config::default()
+        };
+
+        assert_eq!(parse_with(r"k", &case_insensitive).unwrap(), folded('k'));
+        assert_eq!(
+            parse_with(r"-", &case_insensitive).unwrap(),
+            Node::Char('-')
+        );
+        assert_eq!(
+            parse_with(r"[^a-c]", &case_insensitive).unwrap(),
+            Node::Class(Class::new([('A', 'C'), ('a', 'c')]).negate()),
+        );
+        assert_eq!(
+            parse(r"a(?i)b|c").unwrap(),
+            Node::Union(
+                Box::new(concat(Node::Char('a'), folded('b'))),
+                Box::new(folded('c')),
+            ),
+        );
+        assert_eq!(
+            parse_with(r"(?-i)a(?i:b)c", &case_insensitive).unwrap(),
+            concat(Node::Char('a'), concat(folded('b'), Node::Char('c'))),
+        );
+        assert_eq!(
+            parse(r"((?i)a)b").unwrap(),
+            concat(Node::Group(1, None, Box::new(folded('a'))), Node::Char('b')),
+        );
+        assert_eq!(
+            parse(r"(?:a)(b)").unwrap(),
+            concat(
+                Node::Char('a'),
+                Node::Group(1, None, Box::new(Node::Char('b')))
+            ),
+        );
+        assert_eq!(parse(r"(?s).").unwrap(), Node::Any { newline: true });
+    }
+
+    #[test]
+    fn test_repeat() {
+        let repeat = |min, max, greedy| Node::Repeat {
+            node: Box::new(Node::Char('a')),
+            min,
+            max,
+            greedy,
+        };
+
+        assert_eq!(parse(r"a*").unwrap(), Node::Star(Box::new(Node::Char('a'))));
+        assert_eq!(parse(r"a*?").unwrap(), repeat(0, None, false));
+        assert_eq!(parse(r"a+").unwrap(), repeat(1, None, true));
+        assert_eq!(parse(r"a??").unwrap(), repeat(0, Some(1), false));
+        assert_eq!(parse(r"a{3}").unwrap(), repeat(3, Some(3), true));
+        assert_eq!(parse(r"a{3,}").unwrap(), repeat(3, None, true));
+        assert_eq!(parse(r"a{3,5}?").unwrap(), repeat(3, Some(5), false));
+
+        assert_eq!(
+            parse(r"a{x}").unwrap(),
+            Node::Concat(
+                Box::new(Node::Char('a')),
+                Box::new(Node::Concat(
+                    Box::new(Node::Char('{')),
+                    Box::new(Node::Concat(
+                        Box::new(Node::Char('x')),
+                        Box::new(Node::Char('}')),
+                    )),
+                )),
+            ),
+        );
+
+        assert!(parse(r"a{1001}").is_err());
+        assert!(parse_with(
+            r"a{1001}",
+            &Config {
+                repeat_limit: 2000,
+                ..Config::default()
+            }
+        )
+        .is_ok());
+        assert!(parse_with(
+            r"(a{2,}){11}",
+            &Config {
+                repeat_limit: 10,
+                ..Config::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_nest_limit() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        let error = parse(&nested(100_000)).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::NestingTooDeep { limit: 100 });
+        assert_eq!(error.offset(), 100);
+        assert!(parse(&nested(100)).is_ok());
+        assert!(parse_with(
+            &nested(3),
+            &Config {
+                nest_limit: 2,
+                ..Config::default()
+            }
+        )
+        .is_err());
+
+        assert_eq!(parse(&"a".repeat(20_000)).unwrap().groups(), 0);
+        assert_eq!(parse(&"(a)|".repeat(20_000)).unwrap().groups(), 20_000);
+    }
+
+    #[test]
+    fn test_any() {
+        assert_eq!(
+            parse(r"a.\.").unwrap(),
+            Node::Concat(
+                Box::new(Node::Char('a')),
+                Box::new(Node::Concat(
+                    Box::new(Node::Any { newline: false }),
+                    Box::new(Node::Char('.')),
+                )),
+            ),
+        );
+
+        assert_eq!(
+            parse_with(
+                r".",
+                &Config {
+                    flags: Flags {
+                        dot_matches_newline: true,
+                        ..Flags::default()
+                    },
+                    ..Config::default()
+                }
+            )
+            .unwrap(),
+            Node::Any { newline: true },
+        );
+    }
+
+    #[test]
+    fn test_assertion() {
+        let concat = |left, right| Node::Concat(Box::new(left), Box::new(right));
+
+        assert_eq!(
+            parse(r"^\ba$").unwrap(),
+            concat(
+                Node::Assert(Assertion::StartText),
+                concat(
+                    Node::Assert(Assertion::WordBoundary),
+                    concat(Node::Char('a'), Node::Assert(Assertion::EndText)),
+                ),
+            ),
+        );
+
+        assert_eq!(
+            parse(r"\A\B\z\a").unwrap(),
+            concat(
+                Node::Assert(Assertion::StartText),
+                concat(
+                    Node::Assert(Assertion::NotWordBoundary),
+                    concat(Node::Assert(Assertion::EndText), Node::Char('a')),
+                ),
+            ),
+        );
+
+        assert_eq!(
+            parse_with(
+                r"^$",
+                &Config {
+                    flags: Flags {
+                        multi_line: true,
+                        ..Flags::default()
+                    },
+                    ..Config::default()
+                }
+            )
+            .unwrap(),
+            concat(
+                Node::Assert(Assertion::StartLine),
+                Node::Assert(Assertion::EndLine),
+            ),
+        );
+    }
+
     #[test]
     fn test_failure() {
-        assert!(parse(r"a(").is_err());
-        assert!(parse(r"a)").is_err());
+        let error = |pattern| {
+            let error = parse(pattern).unwrap_err();
+            (error.kind(), error.offset())
+        };
+
+        assert_eq!(error(r"a("), (ParseErrorKind::UnclosedGroup, 1));
+        assert_eq!(error(r"a(b(c)"), (ParseErrorKind::UnclosedGroup, 1));
+        assert_eq!(error(r"a)"), (ParseErrorKind::UnopenedGroup, 1));
+        assert_eq!(error(r"ab\"), (ParseErrorKind::DanglingEscape, 2));
+        assert_eq!(error(r"(a\"), (ParseErrorKind::DanglingEscape, 2));
+        assert_eq!(error(r"[a\"), (ParseErrorKind::DanglingEscape, 2));
+        assert_eq!(error(r"[]"), (ParseErrorKind::EmptyClass, 0));
+        assert_eq!(error(r"x[^]"), (ParseErrorKind::EmptyClass, 1));
+        assert_eq!(error(r"[a"), (ParseErrorKind::UnclosedClass, 0));
+        assert_eq!(error(r"[az-a]"), (ParseErrorKind::InvalidClassRange, 2));
+        assert_eq!(error(r"a\p"), (ParseErrorKind::InvalidProperty, 1));
+        assert_eq!(error(r"\p{Greek"), (ParseErrorKind::InvalidProperty, 0));
+        assert_eq!(
+            error(r"[\p{Klingon}]"),
+            (ParseErrorKind::UnknownProperty, 1)
+        );
+        assert_eq!(error(r"(?x)"), (ParseErrorKind::UnknownFlag, 2));
+        assert_eq!(error(r"a(?i-u-m)"), (ParseErrorKind::UnknownFlag, 6));
+        assert_eq!(error(r"(?P<1>a)"), (ParseErrorKind::InvalidGroupName, 4));
+        assert_eq!(error(r"(?<>a)"), (ParseErrorKind::InvalidGroupName, 3));
+        assert_eq!(error(r"(?<a-b>a)"), (ParseErrorKind::InvalidGroupName, 3));
+        assert_eq!(error(r"(?<=a)"), (ParseErrorKind::InvalidGroupName, 3));
+        assert_eq!(error(r"a(?i"), (ParseErrorKind::UnclosedGroup, 1));
+        assert_eq!(error(r"(?i:a"), (ParseErrorKind::UnclosedGroup, 0));
+        assert_eq!(error(r"a**"), (ParseErrorKind::RepetitionMissing, 2));
+        assert_eq!(error(r"+a"), (ParseErrorKind::RepetitionMissing, 0));
+        assert_eq!(error(r"a|*"), (ParseErrorKind::RepetitionMissing, 2));
+        assert_eq!(error(r"(?)"), (ParseErrorKind::RepetitionMissing, 1));
+        assert_eq!(
+            error(r"a{5,3}"),
+            (ParseErrorKind::InvalidRepetitionRange, 1)
+        );
+        assert_eq!(
+            error(r"a{99999999999999999999999}"),
+            (ParseErrorKind::RepetitionTooLarge { limit: 1000 }, 1),
+        );
+        assert_eq!(
+            error(r"é{1001}"),
+            (ParseErrorKind::RepetitionTooLarge { limit: 1000 }, 2),
+        );
+
+        assert_eq!(
+            parse(r"a(").unwrap_err().to_string(),
+            "unclosed group at offset 1, expected `)`",
+        );
     }
 }
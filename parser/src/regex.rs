@@ -0,0 +1,431 @@
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
+/// A match of a regex in a text, given as byte offsets into the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    start: usize,
+    end: usize,
+}
+
+impl Match {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// The spans of the capture groups of a match. Group 0 is the whole match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures {
+    groups: Vec<Option<Match>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl Captures {
+    pub fn new(groups: Vec<Option<Match>>) -> Self {
+        Self {
+            groups,
+            names: Arc::from([]),
+        }
+    }
+
+    /// Names the groups by index, as `Node::group_names` does.
+    pub fn with_names(mut self, names: Arc<[Option<String>]>) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn get(&self, index: usize) -> Option<Match> {
+        self.groups.get(index).cloned().flatten()
+    }
+
+    /// Returns the span of the group with the name. Where several groups have the name,
+    /// it is the leftmost one that took part in the match.
+    pub fn name(&self, name: &str) -> Option<Match> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.as_deref() == Some(name))
+            .find_map(|(index, _)| self.get(index))
+    }
+
+    /// Appends the template to `dst`, with the groups of the match in `text` in place of
+    /// `$1` or `${1}` and of `$name` or `${name}`, and `$` in place of `$$`. An unbraced
+    /// reference takes the longest run of letters, digits and `_`, so `$1a` refers to the
+    /// group `1a`. A group that did not take part in the match, or does not exist, expands
+    /// to nothing, and a `$` that does not start a reference is kept as it is.
+    pub fn expand(&self, text: &str, template: &str, dst: &mut String) {
+        let mut rest = template;
+
+        while let Some(position) = rest.find('$') {
+            dst.push_str(&rest[..position]);
+            rest = &rest[position + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (reference, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) if is_reference(&braced[..end]) => {
+                        (&braced[..end], &braced[end + 1..])
+                    }
+                    _ => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+
+            if reference.is_empty() {
+                dst.push('$');
+                continue;
+            }
+
+            let group = match reference.parse() {
+                Ok(index) => self.get(index),
+                Err(_) => self.name(reference),
+            };
+
+            if let Some(group) = group {
+                dst.push_str(&text[group.range()]);
+            }
+
+            rest = after;
+        }
+
+        dst.push_str(rest);
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match>> + '_ {
+        self.groups.iter().cloned()
+    }
+}
+
+fn is_reference(reference: &str) -> bool {
+    !reference.is_empty()
+        && reference
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// What a match is replaced with: a template, expanded by `Captures::expand`, or a closure
+/// that is given the groups of the match.
+pub trait Replacer {
+    /// Appends the replacement of the match whose groups in `text` are `captures` to `dst`.
+    fn replace_append(&mut self, text: &str, captures: &Captures, dst: &mut String);
+
+    /// Returns the replacement if it is the same for every match, so that the groups need
+    /// not be found.
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        None
+    }
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, text: &str, captures: &Captures, dst: &mut String) {
+        captures.expand(text, self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (!self.contains('$')).then_some(Cow::Borrowed(*self))
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, text: &str, captures: &Captures, dst: &mut String) {
+        self.as_str().replace_append(text, captures, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (!self.contains('$')).then_some(Cow::Borrowed(self.as_str()))
+    }
+}
+
+impl<F: FnMut(&Captures) -> String> Replacer for F {
+    fn replace_append(&mut self, _: &str, captures: &Captures, dst: &mut String) {
+        dst.push_str(&self(captures));
+    }
+}
+
+/// An iterator over the substrings of a text between the matches of a regex.
+///
+/// A match at the start or end of the text yields an empty substring before or after it,
+/// as do two adjacent matches between them.
+#[derive(Debug, Clone)]
+pub struct Split<'t, I> {
+    matches: I,
+    text: &'t str,
+    last_end: usize,
+}
+
+impl<'t, I: Iterator<Item = Match>> Split<'t, I> {
+    /// Splits the text on `matches`, which are the matches of a regex in it.
+    pub fn new(text: &'t str, matches: I) -> Self {
+        Self {
+            matches,
+            text,
+            last_end: 0,
+        }
+    }
+
+    // Returns the rest of the text after the last match, once.
+    fn rest(&mut self) -> Option<&'t str> {
+        if self.last_end > self.text.len() {
+            return None;
+        }
+
+        let rest = &self.text[self.last_end..];
+        self.last_end = self.text.len() + 1;
+        Some(rest)
+    }
+}
+
+impl<'t, I: Iterator<Item = Match>> Iterator for Split<'t, I> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.matches.next() {
+            Some(m) => {
+                let substring = &self.text[self.last_end..m.start()];
+                self.last_end = m.end();
+                Some(substring)
+            }
+            None => self.rest(),
+        }
+    }
+}
+
+/// An iterator over at most `limit` substrings of a text between the matches of a regex,
+/// the last of which is the rest of the text.
+#[derive(Debug, Clone)]
+pub struct SplitN<'t, I> {
+    split: Split<'t, I>,
+    limit: usize,
+}
+
+impl<'t, I: Iterator<Item = Match>> SplitN<'t, I> {
+    pub fn new(text: &'t str, matches: I, limit: usize) -> Self {
+        Self {
+            split: Split::new(text, matches),
+            limit,
+        }
+    }
+}
+
+impl<'t, I: Iterator<Item = Match>> Iterator for SplitN<'t, I> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.limit == 0 {
+            return None;
+        }
+
+        self.limit -= 1;
+
+        match self.limit {
+            0 => self.split.rest(),
+            _ => self.split.next(),
+        }
+    }
+}
+
+/// The interface of every regex engine, so that an application can be written once and pick
+/// the engine at runtime, as a `Box<dyn Regex>`.
+pub trait Regex {
+    fn new(pattern: &str) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    /// Returns true if the regex matches the whole text.
+    fn matches(&self, text: &str) -> bool;
+
+    /// Returns true if the regex matches anywhere in the text.
+    fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the leftmost-first match in the text.
+    fn find(&self, text: &str) -> Option<Match>;
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a>;
+
+    /// Returns the spans of the capture groups of the leftmost-first match in the text.
+    /// An engine that cannot tell the groups apart gives only group 0.
+    fn captures(&self, text: &str) -> Option<Captures> {
+        self.find(text).map(|m| Captures::new(vec![Some(m)]))
+    }
+
+    /// Returns an iterator over the capture groups of all non-overlapping leftmost-first
+    /// matches in the text. An engine that cannot tell the groups apart gives only group 0.
+    fn captures_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Captures> + 'a> {
+        Box::new(self.find_iter(text).map(|m| Captures::new(vec![Some(m)])))
+    }
+}
+
+/// The methods built on the `Regex` interface, which every engine, and `dyn Regex`, gets
+/// once this trait is imported.
+pub trait RegexExt: Regex {
+    /// Returns an iterator over the substrings of the text between the matches.
+    fn split<'a>(&'a self, text: &'a str) -> Split<'a, Box<dyn Iterator<Item = Match> + 'a>> {
+        Split::new(text, self.find_iter(text))
+    }
+
+    /// Returns an iterator over at most `limit` substrings of the text between the matches,
+    /// the last of which is the rest of the text.
+    fn splitn<'a>(
+        &'a self,
+        text: &'a str,
+        limit: usize,
+    ) -> SplitN<'a, Box<dyn Iterator<Item = Match> + 'a>> {
+        SplitN::new(text, self.find_iter(text), limit)
+    }
+
+    /// Replaces the leftmost-first match in the text. See `replacen`.
+    fn replace<'t>(&self, text: &'t str, replacer: impl Replacer) -> Cow<'t, str> {
+        self.replacen(text, 1, replacer)
+    }
+
+    /// Replaces all non-overlapping leftmost-first matches in the text. See `replacen`.
+    fn replace_all<'t>(&self, text: &'t str, replacer: impl Replacer) -> Cow<'t, str> {
+        self.replacen(text, 0, replacer)
+    }
+
+    /// Replaces the first `limit` non-overlapping leftmost-first matches in the text, or all
+    /// of them if `limit` is 0, with a template such as `"$2-$1"` or the string returned by
+    /// a closure given the groups of each match. The text is returned without being copied
+    /// if nothing matches.
+    fn replacen<'t>(
+        &self,
+        text: &'t str,
+        limit: usize,
+        mut replacer: impl Replacer,
+    ) -> Cow<'t, str> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+
+        // The groups are only found when the replacement refers to them.
+        let matches = match replacer.no_expansion() {
+            Some(_) => Box::new(self.find_iter(text).map(|m| Captures::new(vec![Some(m)]))),
+            None => self.captures_iter(text),
+        };
+        let mut matches = matches.take(limit).peekable();
+
+        if matches.peek().is_none() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut replaced = String::with_capacity(text.len());
+        let mut last_end = 0;
+
+        for captures in matches {
+            let m = captures.get(0).unwrap();
+            replaced.push_str(&text[last_end..m.start()]);
+            replacer.replace_append(text, &captures, &mut replaced);
+            last_end = m.end();
+        }
+
+        replaced.push_str(&text[last_end..]);
+        Cow::Owned(replaced)
+    }
+}
+
+impl<R: Regex + ?Sized> RegexExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Captures, Match, Split, SplitN};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_expand() {
+        let text = "abc 12";
+        let captures = Captures::new(vec![
+            Some(Match::new(0, 6)),
+            Some(Match::new(0, 3)),
+            Some(Match::new(4, 6)),
+            None,
+        ])
+        .with_names(Arc::from([
+            None,
+            Some("word".into()),
+            None,
+            Some("x".into()),
+        ]));
+        let expand = |template| {
+            let mut dst = String::new();
+            captures.expand(text, template, &mut dst);
+            dst
+        };
+
+        assert_eq!(captures.name("word"), Some(Match::new(0, 3)));
+        assert_eq!(captures.name("x"), None);
+        assert_eq!(expand("$2-$1"), "12-abc");
+        assert_eq!(expand("$0!"), "abc 12!");
+        assert_eq!(expand("${word}_"), "abc_");
+        assert_eq!(expand("$word_"), "");
+        assert_eq!(expand("${2}0 $20"), "120 ");
+        assert_eq!(expand("$$1 costs $"), "$1 costs $");
+        assert_eq!(expand("$x$3$9"), "");
+        assert_eq!(expand("${} ${a-b} $-"), "${} ${a-b} $-");
+    }
+
+    #[test]
+    fn test_split() {
+        let text = "abcd";
+        let matches = |ranges: &[(usize, usize)]| {
+            ranges
+                .iter()
+                .map(|&(start, end)| Match::new(start, end))
+                .collect::<Vec<_>>()
+        };
+        let split = |ranges| Split::new(text, matches(ranges).into_iter()).collect::<Vec<_>>();
+        let splitn = |ranges, limit| {
+            SplitN::new(text, matches(ranges).into_iter(), limit).collect::<Vec<_>>()
+        };
+
+        assert_eq!(split(&[]), ["abcd"]);
+        assert_eq!(split(&[(0, 1), (3, 4)]), ["", "bc", ""]);
+        assert_eq!(split(&[(1, 2), (2, 3)]), ["a", "", "d"]);
+        assert_eq!(split(&[(0, 0), (1, 1), (4, 4)]), ["", "a", "bcd", ""]);
+        assert_eq!(split(&[(0, 4)]), ["", ""]);
+        assert_eq!(Split::new("", [].into_iter()).collect::<Vec<_>>(), [""]);
+        assert_eq!(splitn(&[(0, 1), (3, 4)], 0), Vec::<&str>::new());
+        assert_eq!(splitn(&[(0, 1), (3, 4)], 1), ["abcd"]);
+        assert_eq!(splitn(&[(0, 1), (3, 4)], 2), ["", "bcd"]);
+        assert_eq!(splitn(&[(0, 1), (3, 4)], 5), ["", "bc", ""]);
+        assert_eq!(splitn(&[(0, 0), (1, 1)], 2), ["", "abcd"]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Patterns and texts to test the engines against the `regex` crate with, which the
+//! `testing` feature enables.
+
+/// Patterns that once matched differently from the `regex` crate on some engine.
+pub const PATTERNS: [&str; 18] = [
+    "a|ab",
+    "(a|b)*ab",
+    "é+|ü",
+    ".",
+    "a.c",
+    "[^a]+",
+    "[α-ω漢]{2,3}",
+    r"\w+",
+    r"\bé",
+    r"\B.\b",
+    r"(?m)^.$",
+    r"\p{Greek}*?a",
+    "(?i)É",
+    "😀|",
+    "",
+    "(|a)*",
+    "(?:a??)+",
+    "(a*?)+",
+];
+
+/// A xorshift generator, so that the random cases are the same on every run.
+#[derive(Debug, Clone)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a random pattern nesting up to `depth` quantifiers, alternations and groups
+    /// around atoms mixing chars of every UTF-8 length, classes, empty patterns and
+    /// assertions.
+    pub fn pattern(&mut self, depth: usize) -> String {
+        let atoms = [
+            "a", "b", "é", "É", "漢", "😀", "", "[a-c]", "[^a]", "[α-ω]", ".", r"\w", r"\s", r"\d",
+            r"\p{L}", "^", "$", r"\b", r"\B", "(?m:^)", "(?m:$)",
+        ];
+
+        if depth == 0 || self.next_u64().is_multiple_of(3) {
+            return atoms[(self.next_u64() % atoms.len() as u64) as usize].into();
+        }
+
+        let (left, right) = (self.pattern(depth - 1), self.pattern(depth - 1));
+
+        match self.next_u64() % 6 {
+            0 | 1 => format!("{left}{right}"),
+            2 => format!("{left}|{right}"),
+            3 => {
+                let quantifier = ["*", "+", "?", "{2}", "{1,3}", "{2,}", "{0,2}"]
+                    [(self.next_u64() % 7) as usize];
+                let lazy = ["", "?"][(self.next_u64() % 2) as usize];
+
+                format!("(?:{left}){quantifier}{lazy}")
+            }
+            4 => format!("({left})"),
+            _ => format!("(?i:{left})"),
+        }
+    }
+
+    /// Returns a random text of up to 7 chars over an alphabet mixing chars of every UTF-8
+    /// length.
+    pub fn text(&mut self) -> String {
+        let alphabet = [
+            'a', 'b', 'c', ' ', '\n', 'é', 'É', 'ü', 'α', 'β', '漢', '😀',
+        ];
+        let len = self.next_u64() % 8;
+
+        (0..len)
+            .map(|_| alphabet[(self.next_u64() % alphabet.len() as u64) as usize])
+            .collect()
+    }
+}
+
+/// Returns the patterns and texts of the differential tests: the fixed patterns followed by
+/// 300 random ones, and 50 random texts.
+pub fn cases() -> (Vec<String>, Vec<String>) {
+    let mut random = Random::new(0x2545_F491_4F6C_DD1D);
+    let texts = (0..50).map(|_| random.text()).collect();
+    let patterns = PATTERNS
+        .into_iter()
+        .map(String::from)
+        .chain((0..300).map(|_| random.pattern(4)))
+        .collect();
+
+    (patterns, texts)
+}
@@ -0,0 +1,35 @@
+// The tables are generated by scripts/generate_unicode_tables.pl.
+
+#[rustfmt::skip]
+pub(crate) mod case_folding;
+#[rustfmt::skip]
+pub(crate) mod general_category;
+#[rustfmt::skip]
+pub(crate) mod perl;
+#[rustfmt::skip]
+pub(crate) mod script;
+
+type Table = &'static [(char, char)];
+
+/// Looks up a general category or a script, such as `Lu`, `Greek` or `sc=Grek`. Case,
+/// spaces, `_` and `-` in the name are ignored.
+pub(crate) fn property(name: &str) -> Option<Table> {
+    let name = name
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .collect::<String>()
+        .to_lowercase();
+    let find = |by_name: &[(&str, Table)], name: &str| {
+        by_name
+            .binary_search_by_key(&name, |(name, _)| name)
+            .ok()
+            .map(|index| by_name[index].1)
+    };
+
+    match name.split_once('=') {
+        Some(("gc" | "generalcategory", value)) => find(general_category::BY_NAME, value),
+        Some(("sc" | "script", value)) => find(script::BY_NAME, value),
+        Some(_) => None,
+        None => find(general_category::BY_NAME, &name).or_else(|| find(script::BY_NAME, &name)),
+    }
+}
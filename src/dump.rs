@@ -0,0 +1,132 @@
+use anyhow::Result;
+use automaton::RegexBuilder as AutomatonBuilder;
+use clap::ValueEnum;
+use parser::{parse, Node};
+use virtual_machine::Regex as VirtualMachine;
+
+/// A form of the pattern to dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Dump {
+    /// The syntax tree
+    Ast,
+    /// The NFA, with and without epsilon transitions
+    Nfa,
+    /// The DFA before minimization
+    Dfa,
+    /// The minimized DFA
+    MinDfa,
+    /// The program of the virtual machine
+    Bytecode,
+}
+
+/// Returns the form of the pattern, as printed by `--dump`.
+pub(crate) fn dump(dump: Dump, pattern: &str) -> Result<String> {
+    let builder = AutomatonBuilder::new(pattern);
+
+    Ok(match dump {
+        Dump::Ast => {
+            let mut tree = String::new();
+            write_tree(&mut tree, &parse(pattern)?, "", "");
+            tree
+        }
+        Dump::Nfa => {
+            let mut nfa = builder.build_nfa()?;
+            let epsilon_nfa = nfa.to_string();
+            nfa.remove_epsilon_transitions();
+
+            format!("# epsilon-NFA\n{epsilon_nfa}\n# epsilon-free NFA\n{nfa}")
+        }
+        Dump::Dfa => builder.build_dfa()?.to_string(),
+        Dump::MinDfa => {
+            let mut dfa = builder.build_dfa()?;
+            dfa.minimize();
+            dfa.to_string()
+        }
+        Dump::Bytecode => VirtualMachine::new(pattern)?.disassemble(),
+    })
+}
+
+// Writes a node on a line starting with `prefix`, and its children below it on lines
+// starting with `indent`, which continues the branches of the nodes above.
+fn write_tree(tree: &mut String, node: &Node, prefix: &str, indent: &str) {
+    let (label, children) = match node {
+        Node::Empty => ("Empty".to_string(), Vec::new()),
+        Node::Char(c) => (format!("Char {c:?}"), Vec::new()),
+        Node::Concat(..) => ("Concat".to_string(), node.concat_items()),
+        Node::Union(..) => ("Union".to_string(), node.alternatives()),
+        Node::Star(node) => ("Star".to_string(), vec![&**node]),
+        Node::Group(index, None, node) => (format!("Group {index}"), vec![&**node]),
+        Node::Group(index, Some(name), node) => (format!("Group {index} {name}"), vec![&**node]),
+        Node::Class(class) => (format!("Class {class}"), Vec::new()),
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            let range = match max {
+                Some(max) if max == min => format!("{{{min}}}"),
+                Some(max) => format!("{{{min},{max}}}"),
+                None => format!("{{{min},}}"),
+            };
+            let lazy = if *greedy { "" } else { " lazy" };
+            (format!("Repeat {range}{lazy}"), vec![&**node])
+        }
+        Node::Any { newline: true } => ("Any".to_string(), Vec::new()),
+        Node::Any { newline: false } => ("Any except \\n".to_string(), Vec::new()),
+        Node::Assert(assertion) => (format!("Assert {assertion}"), Vec::new()),
+    };
+
+    tree.push_str(prefix);
+    tree.push_str(&label);
+    tree.push('\n');
+
+    for (i, child) in children.iter().enumerate() {
+        match i + 1 == children.len() {
+            true => write_tree(
+                tree,
+                child,
+                &format!("{indent}└─ "),
+                &format!("{indent}   "),
+            ),
+            false => write_tree(
+                tree,
+                child,
+                &format!("{indent}├─ "),
+                &format!("{indent}│  "),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{dump, Dump};
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            dump(Dump::Ast, r"a{2}?|[^a-c]\A").unwrap(),
+            concat!(
+                "Union\n",
+                "├─ Repeat {2} lazy\n",
+                "│  └─ Char 'a'\n",
+                "└─ Concat\n",
+                "   ├─ Class [\\0-`d-\\u{10ffff}]\n",
+                "   └─ Assert \\A\n",
+            ),
+        );
+        assert_eq!(
+            dump(Dump::MinDfa, "ab|cb").unwrap(),
+            "start: 0\naccepts: [2]\n0:\n  [ac] => 1\n1:\n  [b] => 2\n2:\n",
+        );
+        assert_eq!(
+            dump(Dump::Bytecode, "a|b").unwrap(),
+            "0: split 1, 3\n1: char 'a'\n2: jmp 4\n3: char 'b'\n4: match\n",
+        );
+        assert!(dump(Dump::Nfa, "a*")
+            .unwrap()
+            .contains("# epsilon-free NFA\n"));
+        assert!(dump(Dump::Dfa, "a(").is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! A regex that picks the engine suited to its pattern.
+
+use anyhow::Result;
+use automaton::{LimitError, RegexBuilder as AutomatonBuilder};
+use parser::{parse, Node};
+use std::{borrow::Cow, fmt, sync::OnceLock};
+use virtual_machine::Regex as VirtualMachine;
+
+pub use parser::{Captures, Match, ParseError, ParseErrorKind, RegexExt, Replacer, Split, SplitN};
+
+// The most states the meta regex lets a DFA have before it falls back to the lazy DFA,
+// which is well below the limit of the DFA engine, as a large DFA is slow to build.
+const DFA_STATE_LIMIT: usize = 1_000;
+
+// The most steps the meta regex lets building a DFA take before it falls back to the lazy
+// DFA, which keeps the build of any pattern within a fraction of a second.
+const DFA_WORK_LIMIT: usize = 1_000_000;
+
+/// An engine chosen for a pattern, which `Regex::engine` gives for the searches and
+/// `Regex::captures_engine` for the groups.
+///
+/// The backtracking VM is never chosen, as every supported feature runs on the DFAs or the
+/// Pike VM and none needs backtracking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// The minimized DFA, for the patterns whose DFA is small.
+    Dfa,
+    /// The lazy DFA, for the patterns whose DFA would be too large to build up front.
+    LazyDfa,
+    /// The Pike VM, for the groups of the patterns that have any, which the DFAs cannot
+    /// tell apart. It is compiled the first time `captures` or a method built on it is
+    /// called.
+    PikeVm,
+}
+
+/// A regex that runs on the engine chosen for its pattern when it is built.
+pub struct Regex {
+    regex: Box<dyn parser::Regex>,
+    engine: Engine,
+    // The node of a pattern with groups, which the DFAs cannot tell apart, and the Pike VM
+    // it is compiled to once they are asked for.
+    groups: Option<(Node, OnceLock<VirtualMachine>)>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        // The pattern is parsed once, for the DFA and, if it is too large, the lazy DFA.
+        let node = parse(pattern)?;
+        let groups = (node.groups() > 0).then(|| (node.clone(), OnceLock::new()));
+        let mut builder = AutomatonBuilder::new(pattern);
+        builder
+            .dfa_state_limit(DFA_STATE_LIMIT)
+            .dfa_work_limit(DFA_WORK_LIMIT);
+
+        let (regex, engine): (Box<dyn parser::Regex>, _) = match builder.build_from(node.clone()) {
+            Ok(regex) => (Box::new(regex), Engine::Dfa),
+            Err(err)
+                if matches!(
+                    err.downcast_ref(),
+                    Some(LimitError::DfaStates { .. } | LimitError::DfaWork { .. })
+                ) =>
+            {
+                (Box::new(builder.build_lazy_from(node)?), Engine::LazyDfa)
+            }
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            regex,
+            engine,
+            groups,
+        })
+    }
+
+    /// Returns the engine of `is_match`, `find` and the other searches.
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Returns the engine of `captures` and the methods built on it, which is the engine of
+    /// the searches for a pattern without groups.
+    pub fn captures_engine(&self) -> Engine {
+        match self.groups {
+            Some(_) => Engine::PikeVm,
+            None => self.engine,
+        }
+    }
+
+    /// Returns true if the regex matches the whole text.
+    pub fn matches(&self, text: &str) -> bool {
+        self.regex.matches(text)
+    }
+
+    /// Returns true if the regex matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Returns the leftmost-first match in the text.
+    pub fn find(&self, text: &str) -> Option<Match> {
+        self.regex.find(text)
+    }
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a> {
+        self.regex.find_iter(text)
+    }
+
+    /// Returns the spans of the capture groups of the leftmost-first match in the text.
+    pub fn captures(&self, text: &str) -> Option<Captures> {
+        match self.groups() {
+            Some(groups) => groups.captures(text),
+            None => self.regex.captures(text),
+        }
+    }
+
+    /// Returns an iterator over the capture groups of all non-overlapping leftmost-first
+    /// matches in the text.
+    pub fn captures_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Captures> + 'a> {
+        match self.groups() {
+            Some(groups) => Box::new(groups.captures_iter(text)),
+            None => self.regex.captures_iter(text),
+        }
+    }
+
+    /// Same as [`RegexExt::split`].
+    pub fn split<'a>(&'a self, text: &'a str) -> Split<'a, Box<dyn Iterator<Item = Match> + 'a>> {
+        RegexExt::split(self, text)
+    }
+
+    /// Same as [`RegexExt::splitn`].
+    pub fn splitn<'a>(
+        &'a self,
+        text: &'a str,
+        limit: usize,
+    ) -> SplitN<'a, Box<dyn Iterator<Item = Match> + 'a>> {
+        RegexExt::splitn(self, text, limit)
+    }
+
+    /// Same as [`RegexExt::replace`].
+    pub fn replace<'t>(&self, text: &'t str, replacer: impl Replacer) -> Cow<'t, str> {
+        RegexExt::replace(self, text, replacer)
+    }
+
+    /// Same as [`RegexExt::replace_all`].
+    pub fn replace_all<'t>(&self, text: &'t str, replacer: impl Replacer) -> Cow<'t, str> {
+        RegexExt::replace_all(self, text, replacer)
+    }
+
+    /// Same as [`RegexExt::replacen`].
+    pub fn replacen<'t>(
+        &self,
+        text: &'t str,
+        limit: usize,
+        replacer: impl Replacer,
+    ) -> Cow<'t, str> {
+        RegexExt::replacen(self, text, limit, replacer)
+    }
+
+    // Returns the Pike VM that finds the groups, if the pattern has any.
+    fn groups(&self) -> Option<&VirtualMachine> {
+        let (node, groups) = self.groups.as_ref()?;
+        Some(groups.get_or_init(|| VirtualMachine::from(node.clone())))
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Regex")
+            .field("engine", &self.engine)
+            .finish_non_exhaustive()
+    }
+}
+
+impl parser::Regex for Regex {
+    fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.matches(text)
+    }
+
+    fn find(&self, text: &str) -> Option<Match> {
+        self.find(text)
+    }
+
+    fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a> {
+        self.find_iter(text)
+    }
+
+    fn captures(&self, text: &str) -> Option<Captures> {
+        self.captures(text)
+    }
+
+    fn captures_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Captures> + 'a> {
+        self.captures_iter(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Captures, Engine, ParseError, Regex, RegexExt};
+
+    #[test]
+    fn test_engine() {
+        let engine = |pattern| Regex::new(pattern).unwrap().engine();
+
+        assert_eq!(engine("a+b|c"), Engine::Dfa);
+        assert_eq!(engine(r"\w+@\w+"), Engine::Dfa);
+        assert_eq!(engine("(?:a|b)*a(?:a|b){14}"), Engine::LazyDfa);
+        assert_eq!(engine("(a)b"), Engine::Dfa);
+        assert_eq!(engine("(a|b)*a(a|b){14}"), Engine::LazyDfa);
+
+        let captures_engine = |pattern| Regex::new(pattern).unwrap().captures_engine();
+
+        assert_eq!(captures_engine("a+b|c"), Engine::Dfa);
+        assert_eq!(captures_engine("(?:a|b)*a(?:a|b){14}"), Engine::LazyDfa);
+        assert_eq!(captures_engine("(a)b"), Engine::PikeVm);
+        assert_eq!(captures_engine("(a|b)*a(a|b){14}"), Engine::PikeVm);
+        assert_eq!(engine("(?:x?){1000}x{1000}"), Engine::LazyDfa);
+
+        // These patterns took seconds to build before the DFA had a work limit, which the
+        // second exceeds within the state limit.
+        for (pattern, engine, is_match) in [
+            (r"(?:\w+\s*){10}", Engine::Dfa, true),
+            (r"(?:\w+\s*){20}", Engine::LazyDfa, false),
+            (
+                r"(?i:(?i:(?:é)(\w{2}é??é)*(?:.+?)+){1,2})??$b+?",
+                Engine::Dfa,
+                false,
+            ),
+        ] {
+            let regex = Regex::new(pattern).unwrap();
+
+            assert_eq!(regex.engine(), engine, "{pattern}");
+            assert_eq!(regex.is_match("a b c d e f g h i j"), is_match);
+        }
+
+        let error = Regex::new("a(").unwrap_err();
+
+        assert!(error.downcast_ref::<ParseError>().is_some());
+    }
+
+    #[test]
+    fn test_search() {
+        for (pattern, engine) in [
+            ("(é|a)+b", Engine::Dfa),
+            ("(?:é|a)+b", Engine::Dfa),
+            ("(?:é|a)+b|(?:a|b)*a(?:a|b){14}", Engine::LazyDfa),
+        ] {
+            let regex = Regex::new(pattern).unwrap();
+
+            assert_eq!(regex.engine(), engine);
+            assert!(regex.matches("aéb"));
+            assert!(regex.is_match("xab"));
+            assert_eq!(regex.find("xéb").map(|m| m.range()), Some(1..4));
+            assert_eq!(
+                regex
+                    .find_iter("ab éb")
+                    .map(|m| m.range())
+                    .collect::<Vec<_>>(),
+                vec![0..2, 3..6],
+            );
+        }
+
+        let regex = Regex::new("(a)(b)?").unwrap();
+        let captures = regex.captures("xa").unwrap();
+
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(1..2));
+        assert_eq!(captures.get(2), None);
+
+        let regex = Regex::new("(a|b)*a(a|b){14}").unwrap();
+        let captures = regex.captures(&"a".repeat(16)).unwrap();
+
+        assert_eq!(regex.engine(), Engine::LazyDfa);
+        assert_eq!(captures.get(1).map(|m| m.range()), Some(0..1));
+        assert_eq!(captures.get(2).map(|m| m.range()), Some(15..16));
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new(r"(?<user>\w+)@(\w+)").unwrap();
+
+        assert_eq!(regex.engine(), Engine::Dfa);
+        assert_eq!(
+            regex.replace_all("a@b, c@d", "$2 at $user"),
+            "b at a, d at c"
+        );
+        assert_eq!(
+            regex
+                .captures_iter("a@b, c@d")
+                .map(|captures| captures.name("user").map(|m| m.range()))
+                .collect::<Vec<_>>(),
+            vec![Some(0..1), Some(5..6)],
+        );
+
+        let regex = Regex::new(r"\d+").unwrap();
+        let text = "1 + 22";
+        let doubled = regex.replace_all(text, |captures: &Captures| {
+            let n: u32 = text[captures.get(0).unwrap().range()].parse().unwrap();
+            (2 * n).to_string()
+        });
+
+        assert_eq!(regex.engine(), Engine::Dfa);
+        assert_eq!(doubled, "2 + 44");
+        assert_eq!(regex.replace(text, "n"), "n + 22");
+
+        let regex = Regex::new("(a)(b)|(?:a|b)*a(?:a|b){14}").unwrap();
+
+        assert_eq!(regex.engine(), Engine::LazyDfa);
+        assert_eq!(regex.replace_all("xab", "$2$1"), "xba");
+    }
+
+    #[test]
+    fn test_dyn_regex() {
+        let build = |pattern| -> Vec<Box<dyn parser::Regex>> {
+            vec![
+                Box::new(<Regex as parser::Regex>::new(pattern).unwrap()),
+                Box::new(<virtual_machine::Regex as parser::Regex>::new(pattern).unwrap()),
+                Box::new(<automaton::Regex as parser::Regex>::new(pattern).unwrap()),
+                Box::new(<automaton::LazyRegex as parser::Regex>::new(pattern).unwrap()),
+            ]
+        };
+
+        for regex in build("(a|é)+b") {
+            assert!(regex.matches("aéb"));
+            assert!(regex.is_match("xab"));
+            assert_eq!(regex.find("xéb").map(|m| m.range()), Some(1..4));
+            assert_eq!(
+                regex
+                    .find_iter("ab éb")
+                    .map(|m| m.range())
+                    .collect::<Vec<_>>(),
+                vec![0..2, 3..6],
+            );
+            assert_eq!(
+                regex.captures("xab").and_then(|captures| captures.get(0)),
+                regex.find("xab"),
+            );
+        }
+
+        for regex in build("(a)b").into_iter().take(2) {
+            assert_eq!(regex.captures("ab").map(|captures| captures.len()), Some(2));
+        }
+
+        let split_cases: [(&str, &str, &[&str]); 8] = [
+            ("[ \t]+", "a b \t  c\td    e", &["a", "b", "c", "d", "e"]),
+            (
+                "X",
+                "lionXXtigerXleopard",
+                &["lion", "", "tiger", "leopard"],
+            ),
+            ("::", "lion::tiger::leopard", &["lion", "tiger", "leopard"]),
+            (r"\s*(,)\s*", "a, b ,c,", &["a", "b", "c", ""]),
+            ("a", "", &[""]),
+            ("a", "a", &["", ""]),
+            ("", "r☃", &["", "r", "☃", ""]),
+            ("a*", "baaab", &["", "b", "b", ""]),
+        ];
+        let splitn_cases: [(&str, &str, usize, &[&str]); 5] = [
+            (r"\W+", "Hey! How are you?", 3, &["Hey", "How", "are you?"]),
+            ("X", "lionXXtigerXleopard", 2, &["lion", "XtigerXleopard"]),
+            (r"\s*(,)\s*", "a, b ,c,", 2, &["a", "b ,c,"]),
+            ("a", "a", 2, &["", ""]),
+            ("", "abc", 3, &["", "a", "bc"]),
+        ];
+
+        for (pattern, text, expected) in split_cases {
+            for regex in build(pattern) {
+                assert_eq!(regex.split(text).collect::<Vec<_>>(), expected, "{pattern}");
+            }
+        }
+
+        for (pattern, text, limit, expected) in splitn_cases {
+            for regex in build(pattern) {
+                assert_eq!(
+                    regex.splitn(text, limit).collect::<Vec<_>>(),
+                    expected,
+                    "{pattern}"
+                );
+            }
+        }
+    }
+}
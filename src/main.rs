@@ -1,24 +1,60 @@
-use automaton::Regex as Automaton;
-use clap::{Parser, ValueEnum};
+use anyhow::Result;
+use automaton::{LazyRegex as LazyAutomaton, ParseError, Regex as Automaton};
+use clap::{Parser, Subcommand, ValueEnum};
+use dump::Dump;
+use parser::Regex;
+use regex_engine::Regex as Auto;
+use replace::ReplaceArgs;
+use search::SearchArgs;
+use std::process;
 use virtual_machine::Regex as VirtualMachine;
 
+mod dump;
+mod replace;
+mod search;
+
 /// String matcher by regular expression
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Regular expression pattern
-    pattern: String,
+    #[arg(required = true)]
+    pattern: Option<String>,
     /// Target text
-    text: String,
+    #[arg(required_unless_present = "dump", conflicts_with = "dump")]
+    text: Option<String>,
+    /// Print an intermediate form of the pattern instead of matching it
+    #[arg(value_enum, long, value_name = "FORM")]
+    dump: Option<Dump>,
     /// Engine type
-    #[arg(value_enum, short = 't', long = "type", default_value_t = EnginType::Automaton)]
+    #[arg(
+        value_enum,
+        short = 't',
+        long = "type",
+        default_value_t = EnginType::Auto,
+        global = true
+    )]
     engine_type: EnginType,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    Search(SearchArgs),
+    Replace(ReplaceArgs),
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 enum EnginType {
+    /// The engine suited to the pattern
+    Auto,
     /// Deterministic finite automaton
     #[value(name = "dfa", alias = "automaton")]
     Automaton,
+    /// Deterministic finite automaton built lazily while matching
+    #[value(name = "lazy-dfa", alias = "lazy-automaton")]
+    LazyAutomaton,
     /// Virtual machine
     #[value(name = "vm", alias = "virtual-machine")]
     VirtualMachine,
@@ -27,26 +63,76 @@ enum EnginType {
 fn main() {
     let args = Args::parse();
 
-    match args.engine_type {
-        EnginType::Automaton => match Automaton::new(&args.pattern) {
-            Ok(regex) => {
-                if regex.matches(&args.text) {
-                    println!("Matched");
-                } else {
-                    eprintln!("Unmatched")
-                }
+    if let Some(command) = &args.command {
+        let pattern = match command {
+            Command::Search(search) => &search.pattern,
+            Command::Replace(replace) => &replace.pattern,
+        };
+
+        let status = match build(&args.engine_type, pattern) {
+            Ok(regex) => match command {
+                Command::Search(search) => search::search(search, regex.as_ref()),
+                Command::Replace(replace) => replace::replace(replace, regex.as_ref()),
+            },
+            Err(err) => {
+                report(pattern, &err);
+                2
             }
-            Err(err) => eprintln!("{}", err),
-        },
-        EnginType::VirtualMachine => match VirtualMachine::new(&args.pattern) {
-            Ok(regex) => {
-                if regex.matches(&args.text) {
-                    println!("Matched");
-                } else {
-                    eprintln!("Unmatched")
-                }
+        };
+
+        process::exit(status);
+    }
+
+    let Some(pattern) = &args.pattern else {
+        unreachable!("clap requires the pattern without a subcommand");
+    };
+
+    if let Some(form) = args.dump {
+        match dump::dump(form, pattern) {
+            Ok(dump) => print!("{dump}"),
+            Err(err) => report(pattern, &err),
+        }
+
+        return;
+    }
+
+    let Some(text) = &args.text else {
+        unreachable!("clap requires the text without `--dump`");
+    };
+
+    match build(&args.engine_type, pattern) {
+        Ok(regex) => {
+            if regex.matches(text) {
+                println!("Matched");
+            } else {
+                eprintln!("Unmatched")
             }
-            Err(err) => eprintln!("{}", err),
-        },
+        }
+        Err(err) => report(pattern, &err),
+    }
+}
+
+fn build(engine_type: &EnginType, pattern: &str) -> Result<Box<dyn Regex>> {
+    match engine_type {
+        EnginType::Auto => build_with::<Auto>(pattern),
+        EnginType::Automaton => build_with::<Automaton>(pattern),
+        EnginType::LazyAutomaton => build_with::<LazyAutomaton>(pattern),
+        EnginType::VirtualMachine => build_with::<VirtualMachine>(pattern),
+    }
+}
+
+fn build_with<R: Regex + 'static>(pattern: &str) -> Result<Box<dyn Regex>> {
+    Ok(Box::new(R::new(pattern)?))
+}
+
+fn report(pattern: &str, err: &anyhow::Error) {
+    match err.downcast_ref::<ParseError>() {
+        Some(err) => {
+            let column = pattern[..err.offset()].chars().count();
+            eprintln!("error: {}", err);
+            eprintln!("    {}", pattern);
+            eprintln!("    {}^", " ".repeat(column));
+        }
+        None => eprintln!("error: {}", err),
     }
 }
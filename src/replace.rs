@@ -0,0 +1,149 @@
+use crate::search::walk;
+use parser::{Regex, RegexExt};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Print the lines of files or stdin with the matches of the pattern replaced
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct ReplaceArgs {
+    /// Regular expression pattern
+    pub(crate) pattern: String,
+    /// Replacement, in which `$1` or `${1}` stands for a group, `$name` or `${name}` for a
+    /// named group and `$$` for `$`
+    replacement: String,
+    /// Files, or `-` for stdin, which is read when none is given
+    paths: Vec<PathBuf>,
+    /// Replace only the first NUM matches of each line, or all of them if NUM is 0
+    #[arg(short, long, value_name = "NUM", default_value_t = 0)]
+    limit: usize,
+}
+
+/// Prints the files or stdin with the matches replaced, returning the exit status: 0 if a
+/// match was replaced, 1 if none was, and 2 if an error occurred.
+pub(crate) fn replace(args: &ReplaceArgs, regex: &dyn Regex) -> i32 {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut replaced = false;
+    let mut failed = false;
+    let mut report = |path: &Path, err: io::Error| {
+        eprintln!("regex-engine: {}: {}", path.display(), err);
+        failed = true;
+    };
+
+    let paths = match args.paths.is_empty() {
+        true => vec![PathBuf::from("-")],
+        false => args.paths.clone(),
+    };
+
+    for path in walk(&paths, false, &mut report) {
+        let result = match path.to_str() {
+            Some("-") => replace_lines(args, regex, io::stdin().lock(), &mut out),
+            _ => File::open(&path)
+                .and_then(|file| replace_lines(args, regex, BufReader::new(file), &mut out)),
+        };
+
+        match result {
+            Ok(count) => replaced |= count > 0,
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return 2,
+            Err(err) => report(&path, err),
+        }
+    }
+
+    if out.flush().is_err() || failed {
+        2
+    } else if replaced {
+        0
+    } else {
+        1
+    }
+}
+
+/// Writes the lines of `reader` to `out` with the matches replaced, returning how many
+/// lines had a match.
+fn replace_lines(
+    args: &ReplaceArgs,
+    regex: &dyn Regex,
+    mut reader: impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<usize> {
+    let mut buffer = Vec::new();
+    let mut count = 0;
+
+    loop {
+        buffer.clear();
+
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(count);
+        }
+
+        let newline = buffer.last() == Some(&b'\n');
+
+        if newline {
+            buffer.pop();
+        }
+
+        let line = String::from_utf8_lossy(&buffer);
+
+        // The line is only copied when a match was replaced.
+        let line = regex.replacen(&line, args.limit, args.replacement.as_str());
+
+        if let Cow::Owned(_) = line {
+            count += 1;
+        }
+
+        out.write_all(line.as_bytes())?;
+
+        if newline {
+            out.write_all(b"\n")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::replace::{replace_lines, ReplaceArgs};
+    use clap::Parser;
+    use parser::Regex;
+
+    #[derive(Debug, Parser)]
+    struct Args {
+        #[command(flatten)]
+        replace: ReplaceArgs,
+    }
+
+    fn replace(args: &[&str], text: &str) -> (String, usize) {
+        let args = Args::parse_from(["replace"].iter().chain(args)).replace;
+        let regex = regex_engine::Regex::new(&args.pattern).unwrap();
+        let mut out = Vec::new();
+        let count = replace_lines(&args, &regex as &dyn Regex, text.as_bytes(), &mut out).unwrap();
+
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn test_replace() {
+        let text = "foo bar\nbaz\nqux";
+
+        assert_eq!(
+            replace(&["ba(.)", "<$1>"], text),
+            ("foo <r>\n<z>\nqux".into(), 2)
+        );
+        assert_eq!(
+            replace(&["a|o", "_"], text),
+            ("f__ b_r\nb_z\nqux".into(), 2)
+        );
+        assert_eq!(
+            replace(&["-l1", "a|o", "_"], text),
+            ("f_o bar\nb_z\nqux".into(), 2)
+        );
+        assert_eq!(
+            replace(&["(?<first>\\w+) (\\w+)", "$2 ${first}"], text),
+            ("bar foo\nbaz\nqux".into(), 1),
+        );
+        assert_eq!(replace(&["$", "$$"], "a\n\nb\n"), ("a$\n$\nb$\n".into(), 3));
+        assert_eq!(replace(&["quux", "x"], text), (text.into(), 0));
+    }
+}
@@ -0,0 +1,321 @@
+use parser::Regex;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const STDIN: &str = "(standard input)";
+
+/// Print the lines of files or stdin that match the pattern
+#[derive(Debug, Clone, clap::Args)]
+pub(crate) struct SearchArgs {
+    /// Regular expression pattern
+    pub(crate) pattern: String,
+    /// Files or directories to search, or `-` for stdin, which is searched when none is given
+    paths: Vec<PathBuf>,
+    /// Print the line number before each line
+    #[arg(short = 'n', long)]
+    line_number: bool,
+    /// Print only the number of selected lines of each file
+    #[arg(short, long)]
+    count: bool,
+    /// Select the lines that do not match
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+    /// Print only the matched parts of the lines, each on a line of its own
+    #[arg(short, long)]
+    only_matching: bool,
+    /// Print NUM lines after each selected line
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+    /// Print NUM lines before each selected line
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+    /// Print NUM lines before and after each selected line
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+    /// Search the files in directories and their subdirectories
+    #[arg(short, long)]
+    recursive: bool,
+}
+
+/// Searches the files or stdin as `grep` does, returning its exit status: 0 if a line was
+/// selected, 1 if none was, and 2 if an error occurred.
+pub(crate) fn search(args: &SearchArgs, regex: &dyn Regex) -> i32 {
+    let with_filename = args.paths.len() > 1 || args.recursive;
+    let mut searcher = Searcher::new(args, regex, BufWriter::new(io::stdout().lock()));
+    let mut selected = false;
+    let mut failed = false;
+    let mut report = |path: &Path, err: io::Error| {
+        eprintln!("regex-engine: {}: {}", path.display(), err);
+        failed = true;
+    };
+
+    let paths = match args.paths.is_empty() {
+        true => vec![PathBuf::from("-")],
+        false => args.paths.clone(),
+    };
+
+    for path in walk(&paths, args.recursive, &mut report) {
+        let result = match path.to_str() {
+            Some("-") => searcher.search(STDIN, io::stdin().lock(), with_filename),
+            _ => File::open(&path).and_then(|file| {
+                let name = path.display().to_string();
+                searcher.search(&name, BufReader::new(file), with_filename)
+            }),
+        };
+
+        match result {
+            Ok(count) => selected |= count > 0,
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return 2,
+            Err(err) => report(&path, err),
+        }
+    }
+
+    if searcher.out.flush().is_err() || failed {
+        2
+    } else if selected {
+        0
+    } else {
+        1
+    }
+}
+
+// Lists the files to search in order, expanding the directories when `recursive` is set.
+// Symbolic links to directories are not followed.
+pub(crate) fn walk(
+    paths: &[PathBuf],
+    recursive: bool,
+    report: &mut impl FnMut(&Path, io::Error),
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut stack = Vec::from_iter(paths.iter().rev().cloned());
+
+    while let Some(path) = stack.pop() {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+
+        if !recursive {
+            report(&path, io::Error::other("Is a directory"));
+            continue;
+        }
+
+        let entries = fs::read_dir(&path).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| (entry.path(), entry.file_type())))
+                .collect::<io::Result<Vec<_>>>()
+        });
+
+        match entries {
+            Ok(mut entries) => {
+                entries.sort_by(|(left, _), (right, _)| right.cmp(left));
+
+                for (path, file_type) in entries {
+                    match file_type {
+                        Ok(file_type) if file_type.is_symlink() && path.is_dir() => {}
+                        Ok(_) => stack.push(path),
+                        Err(err) => report(&path, err),
+                    }
+                }
+            }
+            Err(err) => report(&path, err),
+        }
+    }
+
+    files
+}
+
+struct Searcher<'r, W> {
+    regex: &'r dyn Regex,
+    out: W,
+    line_number: bool,
+    count: bool,
+    invert_match: bool,
+    only_matching: bool,
+    after_context: usize,
+    before_context: usize,
+    // Whether a group of lines has been printed, after which the next group that does not
+    // follow it is set apart by `--`.
+    printed: bool,
+}
+
+impl<'r, W: Write> Searcher<'r, W> {
+    fn new(args: &SearchArgs, regex: &'r dyn Regex, out: W) -> Self {
+        Self {
+            regex,
+            out,
+            line_number: args.line_number,
+            count: args.count,
+            invert_match: args.invert_match,
+            only_matching: args.only_matching,
+            after_context: args.after_context.or(args.context).unwrap_or(0),
+            before_context: args.before_context.or(args.context).unwrap_or(0),
+            printed: false,
+        }
+    }
+
+    /// Prints the selected lines of `reader`, returning how many there were.
+    fn search(
+        &mut self,
+        name: &str,
+        mut reader: impl BufRead,
+        with_filename: bool,
+    ) -> io::Result<usize> {
+        let name = with_filename.then_some(name);
+        let context = !self.count && !self.only_matching;
+        let mut buffer = Vec::new();
+        let mut before = VecDeque::new();
+        let mut after = 0;
+        let mut last_printed = None;
+        let mut count = 0;
+
+        for number in 1.. {
+            buffer.clear();
+
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+
+            if buffer.last() == Some(&b'\n') {
+                buffer.pop();
+            }
+
+            let line = String::from_utf8_lossy(&buffer);
+
+            if self.regex.is_match(&line) == self.invert_match {
+                if context && after > 0 {
+                    after -= 1;
+                    self.print(name, number, '-', &line)?;
+                    last_printed = Some(number);
+                } else if context && self.before_context > 0 {
+                    if before.len() == self.before_context {
+                        before.pop_front();
+                    }
+
+                    before.push_back((number, line.into_owned()));
+                }
+
+                continue;
+            }
+
+            count += 1;
+
+            if self.count {
+                continue;
+            }
+
+            if self.only_matching {
+                if !self.invert_match {
+                    for m in self.regex.find_iter(&line).filter(|m| !m.is_empty()) {
+                        self.print(name, number, ':', &line[m.range()])?;
+                    }
+                }
+
+                continue;
+            }
+
+            let first = before.front().map_or(number, |(number, _)| *number);
+            let follows = last_printed.is_some_and(|last| last + 1 == first);
+
+            if self.printed && !follows && (self.after_context > 0 || self.before_context > 0) {
+                writeln!(self.out, "--")?;
+            }
+
+            for (number, line) in before.drain(..) {
+                self.print(name, number, '-', &line)?;
+            }
+
+            self.print(name, number, ':', &line)?;
+            last_printed = Some(number);
+            after = self.after_context;
+        }
+
+        if self.count {
+            match name {
+                Some(name) => writeln!(self.out, "{name}:{count}")?,
+                None => writeln!(self.out, "{count}")?,
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn print(
+        &mut self,
+        name: Option<&str>,
+        number: usize,
+        separator: char,
+        text: &str,
+    ) -> io::Result<()> {
+        if let Some(name) = name {
+            write!(self.out, "{name}{separator}")?;
+        }
+
+        if self.line_number {
+            write!(self.out, "{number}{separator}")?;
+        }
+
+        self.printed = true;
+        writeln!(self.out, "{text}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::{SearchArgs, Searcher};
+    use clap::Parser;
+    use parser::Regex;
+
+    #[derive(Debug, Parser)]
+    struct Args {
+        #[command(flatten)]
+        search: SearchArgs,
+    }
+
+    fn search(args: &[&str], text: &str) -> (String, usize) {
+        let args = Args::parse_from(["search"].iter().chain(args)).search;
+        let regex = regex_engine::Regex::new(&args.pattern).unwrap();
+        let mut searcher = Searcher::new(&args, &regex as &dyn Regex, Vec::new());
+        let count = searcher.search("file", text.as_bytes(), false).unwrap();
+
+        (String::from_utf8(searcher.out).unwrap(), count)
+    }
+
+    #[test]
+    fn test_search() {
+        let text = "foo\nbar\nbaz\nqux\nfoo bar\n";
+
+        assert_eq!(search(&["ba."], text), ("bar\nbaz\nfoo bar\n".into(), 3));
+        assert_eq!(
+            search(&["-n", "^foo"], text),
+            ("1:foo\n5:foo bar\n".into(), 2)
+        );
+        assert_eq!(search(&["-c", "a"], text), ("3\n".into(), 3));
+        assert_eq!(search(&["-v", "a"], text), ("foo\nqux\n".into(), 2));
+        assert_eq!(search(&["-cv", "a"], text), ("2\n".into(), 2));
+        assert_eq!(search(&["-o", "ba."], text), ("bar\nbaz\nbar\n".into(), 3));
+        assert_eq!(search(&["-o", "a*"], "baab\n"), ("aa\n".into(), 1));
+        assert_eq!(search(&["quux"], text), ("".into(), 0));
+        assert_eq!(search(&["é$"], "café\r\ncafé"), ("café\n".into(), 1));
+    }
+
+    #[test]
+    fn test_context() {
+        let text = "1\n2\nx\n4\n5\n6\n7\nx\n9\n";
+
+        assert_eq!(search(&["-A1", "x"], text).0, "x\n4\n--\nx\n9\n");
+        assert_eq!(search(&["-B", "2", "x"], text).0, "1\n2\nx\n--\n6\n7\nx\n");
+        assert_eq!(
+            search(&["-nC3", "x"], text).0,
+            "1-1\n2-2\n3:x\n4-4\n5-5\n6-6\n7-7\n8:x\n9-9\n",
+        );
+        assert_eq!(
+            search(&["-n", "-C1", "-A0", "x"], text).0,
+            "2-2\n3:x\n--\n7-7\n8:x\n"
+        );
+    }
+}
@@ -1,13 +1,31 @@
-use parser::Node;
+use parser::{Assertion, Class, Node};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Instruction {
     C(char),
+    R(Class),
     J(usize),
     S(usize, usize),
+    Save(usize),
+    Assert(Assertion),
     M,
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::C(c) => write!(f, "char {c:?}"),
+            Self::R(class) => write!(f, "class {class}"),
+            Self::J(x) => write!(f, "jmp {x}"),
+            Self::S(x, y) => write!(f, "split {x}, {y}"),
+            Self::Save(slot) => write!(f, "save {slot}"),
+            Self::Assert(assertion) => write!(f, "assert {assertion}"),
+            Self::M => write!(f, "match"),
+        }
+    }
+}
+
 trait CompileHelper {
     fn recur(&self, n: usize) -> (Vec<Instruction>, usize);
 }
@@ -23,23 +41,40 @@ impl CompileHelper for Node {
                 instructions.push(Instruction::C(*c));
                 n += 1;
             }
-            Node::Concat(left, right) => {
-                let (instructions1, n1) = left.recur(n);
-                let (instructions2, n2) = right.recur(n1);
+            // Chains of concatenations and unions are compiled in a loop, as they nest as
+            // deeply as they are long.
+            Node::Concat(..) => {
+                for item in self.concat_items() {
+                    let (instructions1, n1) = item.recur(n);
 
-                instructions.extend(instructions1);
-                instructions.extend(instructions2);
-                n = n2;
+                    instructions.extend(instructions1);
+                    n = n1;
+                }
             }
-            Node::Union(left, right) => {
-                let (instructions1, n1) = left.recur(n + 1);
-                let (instructions2, n2) = right.recur(n1 + 1);
+            Node::Union(..) => {
+                let base = n;
+                let mut alternatives = self.alternatives();
+                let last = alternatives.pop().unwrap();
+                let mut jumps = Vec::new();
+
+                for alternative in alternatives {
+                    let (instructions1, n1) = alternative.recur(n + 1);
+
+                    instructions.push(Instruction::S(n + 1, n1 + 1));
+                    instructions.extend(instructions1);
+                    instructions.push(Instruction::J(0));
+                    jumps.push(n1);
+                    n = n1 + 1;
+                }
+
+                let (instructions1, n1) = last.recur(n);
 
-                instructions.push(Instruction::S(n + 1, n1 + 1));
                 instructions.extend(instructions1);
-                instructions.push(Instruction::J(n2));
-                instructions.extend(instructions2);
-                n = n2;
+                n = n1;
+
+                for pc in jumps {
+                    instructions[pc - base] = Instruction::J(n);
+                }
             }
             Node::Star(node) => {
                 if node.is_star() {
@@ -47,18 +82,141 @@ impl CompileHelper for Node {
                 } else {
                     let (instructions1, n1) = node.recur(n + 1);
 
+                    // The loop ends in a split rather than a jump back to the first one, so
+                    // that a thread whose iteration matched nothing can still leave the loop
+                    // at the position where the first split was already visited.
                     instructions.push(Instruction::S(n + 1, n1 + 1));
                     instructions.extend(instructions1);
-                    instructions.push(Instruction::J(n));
+                    instructions.push(Instruction::S(n + 1, n1 + 1));
                     n = n1 + 1;
                 }
             }
+            Node::Class(class) => {
+                instructions.push(Instruction::R(class.clone()));
+                n += 1;
+            }
+            Node::Any { newline } => {
+                instructions.push(Instruction::R(Class::any(*newline)));
+                n += 1;
+            }
+            Node::Assert(assertion) => {
+                instructions.push(Instruction::Assert(*assertion));
+                n += 1;
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                let base = n;
+
+                // `x{n,}` loops back into its last copy of `x`, as the DFAs do.
+                let copies = match max {
+                    None => min.saturating_sub(1),
+                    Some(_) => *min,
+                };
+
+                for _ in 0..copies {
+                    let (instructions1, n1) = node.recur(n);
+
+                    instructions.extend(instructions1);
+                    n = n1;
+                }
+
+                match max {
+                    None if *min > 0 => {
+                        let (instructions1, n1) = node.recur(n);
+
+                        instructions.extend(instructions1);
+                        instructions.push(split(n, n1 + 1, *greedy));
+                        n = n1 + 1;
+                    }
+                    None => {
+                        let (instructions1, n1) = node.recur(n + 1);
+
+                        instructions.push(split(n + 1, n1 + 1, *greedy));
+                        instructions.extend(instructions1);
+                        instructions.push(split(n + 1, n1 + 1, *greedy));
+                        n = n1 + 1;
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+
+                        for _ in *min..*max {
+                            let (instructions1, n1) = node.recur(n + 1);
+
+                            splits.push(n);
+                            instructions.push(Instruction::S(0, 0));
+                            instructions.extend(instructions1);
+                            n = n1;
+                        }
+
+                        for pc in splits {
+                            instructions[pc - base] = split(pc + 1, n, *greedy);
+                        }
+                    }
+                }
+            }
+            Node::Group(index, _, node) => {
+                let (instructions1, n1) = node.recur(n + 1);
+
+                instructions.push(Instruction::Save(2 * index));
+                instructions.extend(instructions1);
+                instructions.push(Instruction::Save(2 * index + 1));
+                n = n1 + 1;
+            }
         }
 
         (instructions, n)
     }
 }
 
+fn split(preferred: usize, other: usize, greedy: bool) -> Instruction {
+    if greedy {
+        Instruction::S(preferred, other)
+    } else {
+        Instruction::S(other, preferred)
+    }
+}
+
+/// Returns the length of the program `compile` would make for the node, without making it,
+/// saturating at `usize::MAX`.
+pub(crate) fn count_instructions(node: &Node) -> usize {
+    fn count(node: &Node) -> usize {
+        match node {
+            Node::Empty => 0,
+            Node::Char(_) | Node::Class(_) | Node::Any { .. } | Node::Assert(_) => 1,
+            Node::Concat(..) => node
+                .concat_items()
+                .into_iter()
+                .map(count)
+                .fold(0, usize::saturating_add),
+            // Each alternative but the last adds a split and a jump.
+            Node::Union(..) => node
+                .alternatives()
+                .into_iter()
+                .map(|alternative| count(alternative).saturating_add(2))
+                .fold(0, usize::saturating_add)
+                .saturating_sub(2),
+            Node::Star(node) if node.is_star() => count(node),
+            Node::Star(node) | Node::Group(_, _, node) => count(node).saturating_add(2),
+            Node::Repeat { node, min, max, .. } => {
+                let inner = count(node);
+                match max {
+                    None if *min > 0 => min.saturating_mul(inner).saturating_add(1),
+                    None => inner.saturating_add(2),
+                    Some(max) => min
+                        .saturating_mul(inner)
+                        .saturating_add((max - min).saturating_mul(inner.saturating_add(1))),
+                }
+            }
+        }
+    }
+
+    count(node).saturating_add(1)
+}
+
 pub(crate) trait Compiler {
     fn compile(&self) -> Vec<Instruction>;
 }
@@ -73,8 +231,8 @@ impl Compiler for Node {
 
 #[cfg(test)]
 mod tests {
-    use crate::compiler::{Compiler, Instruction};
-    use parser::Node;
+    use crate::compiler::{count_instructions, Compiler, Instruction};
+    use parser::{parse, Assertion, Class, Node};
 
     #[test]
     fn test_compile() {
@@ -123,7 +281,7 @@ mod tests {
                 Instruction::S(1, 4),
                 Instruction::C('a'),
                 Instruction::C('b'),
-                Instruction::J(0),
+                Instruction::S(1, 4),
                 Instruction::M,
             ],
         );
@@ -138,9 +296,102 @@ mod tests {
                 Instruction::S(1, 4),
                 Instruction::C('a'),
                 Instruction::C('b'),
-                Instruction::J(0),
+                Instruction::S(1, 4),
                 Instruction::M,
             ],
         );
+
+        assert_eq!(
+            Node::Star(Box::new(Node::Group(1, None, Box::new(Node::Char('a'))))).compile(),
+            vec![
+                Instruction::S(1, 5),
+                Instruction::Save(2),
+                Instruction::C('a'),
+                Instruction::Save(3),
+                Instruction::S(1, 5),
+                Instruction::M,
+            ],
+        );
+
+        assert_eq!(
+            Node::Concat(
+                Box::new(Node::Char('a')),
+                Box::new(Node::Class(Class::new([('0', '9')]))),
+            )
+            .compile(),
+            vec![
+                Instruction::C('a'),
+                Instruction::R(Class::new([('0', '9')])),
+                Instruction::M,
+            ],
+        );
+
+        assert_eq!(
+            Node::Repeat {
+                node: Box::new(Node::Char('a')),
+                min: 1,
+                max: Some(3),
+                greedy: true,
+            }
+            .compile(),
+            vec![
+                Instruction::C('a'),
+                Instruction::S(2, 5),
+                Instruction::C('a'),
+                Instruction::S(4, 5),
+                Instruction::C('a'),
+                Instruction::M,
+            ],
+        );
+
+        assert_eq!(
+            Node::Repeat {
+                node: Box::new(Node::Char('a')),
+                min: 1,
+                max: None,
+                greedy: false,
+            }
+            .compile(),
+            vec![Instruction::C('a'), Instruction::S(2, 0), Instruction::M],
+        );
+
+        assert_eq!(
+            Node::Concat(
+                Box::new(Node::Assert(Assertion::WordBoundary)),
+                Box::new(Node::Char('a')),
+            )
+            .compile(),
+            vec![
+                Instruction::Assert(Assertion::WordBoundary),
+                Instruction::C('a'),
+                Instruction::M,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_count_instructions() {
+        for pattern in [
+            "",
+            "a",
+            "abc",
+            "a|b|c",
+            "(ab|c*)d?",
+            "(a*)*",
+            "a{3}",
+            "a{2,}",
+            "(a|b){0,3}?",
+            "a{0}",
+            r"^\bx+$",
+        ] {
+            let node = parse(pattern).unwrap();
+
+            assert_eq!(node.compile().len(), count_instructions(&node), "{pattern}");
+        }
+
+        assert_eq!(
+            count_instructions(&parse("(a{1000}){1000}").unwrap()),
+            1_002_001
+        );
     }
 }
@@ -1,35 +1,339 @@
+//! A regex engine that compiles a pattern into a program of instructions and runs it on a
+//! virtual machine, either a Pike VM or a backtracking one.
+//!
+//! Every offset in the API is a byte offset into the text, and falls on a char boundary:
+//! the ends of a `Match` and of the groups in `Captures` can be used to slice the text.
+//! The machines work on the same offsets, stepping over each char by its UTF-8 length.
+
 use anyhow::Result;
-use compiler::{Compiler, Instruction};
-use parser::parse;
+use compiler::{count_instructions, Compiler, Instruction};
+use parser::{parse_with, Config, Node};
+use pike_vm::PikeVM;
+use std::{fmt, sync::Arc};
 use virtual_machine::VirtualMachine;
 
+pub use parser::{Captures, Match, ParseError, ParseErrorKind, RegexExt, Replacer, Split, SplitN};
+
 pub(crate) mod compiler;
+pub(crate) mod pike_vm;
 pub(crate) mod virtual_machine;
 
+/// How the compiled program is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Runs all threads in lockstep in O(n·m) time.
+    #[default]
+    PikeVM,
+    /// Tries one thread at a time, depth-first.
+    Backtrack,
+}
+
+/// An error for a pattern whose program would exceed a limit set on the `RegexBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    ProgramLength { limit: usize },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProgramLength { limit } => {
+                write!(f, "the program would have more than {limit} instructions")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexBuilder {
+    pattern: String,
+    config: Config,
+    mode: Mode,
+    program_length_limit: usize,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            config: Config::default(),
+            mode: Mode::default(),
+            program_length_limit: 100_000,
+        }
+    }
+
+    /// Sets the deepest nesting of groups allowed.
+    pub fn nest_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.nest_limit = limit;
+        self
+    }
+
+    /// Sets the largest number of instructions the pattern may compile to.
+    pub fn program_length_limit(&mut self, limit: usize) -> &mut Self {
+        self.program_length_limit = limit;
+        self
+    }
+
+    /// Sets the largest count allowed in a bounded repetition such as `a{n,m}`.
+    pub fn repeat_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.repeat_limit = limit;
+        self
+    }
+
+    pub fn mode(&mut self, mode: Mode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Lets `.` match `\n`.
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.dot_matches_newline = yes;
+        self
+    }
+
+    /// Lets `^` and `$` match at the start and end of each line.
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.multi_line = yes;
+        self
+    }
+
+    /// Matches letters regardless of their case, as `(?i)` does.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.case_insensitive = yes;
+        self
+    }
+
+    /// Makes `\d`, `\s`, `\w` and `\b` Unicode-aware. Enabled by default.
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.config.flags.unicode = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex> {
+        let node = parse_with(&self.pattern, &self.config)?;
+
+        if count_instructions(&node) > self.program_length_limit {
+            let limit = self.program_length_limit;
+            return Err(LimitError::ProgramLength { limit }.into());
+        }
+
+        Ok(Regex {
+            mode: self.mode,
+            ..Regex::from(node)
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Regex {
     instructions: Vec<Instruction>,
+    slots: usize,
+    names: Arc<[Option<String>]>,
+    mode: Mode,
 }
 
 impl Regex {
     pub fn new(pattern: &str) -> Result<Self> {
-        let node = parse(pattern)?;
-        let instructions = node.compile();
-        Ok(Self { instructions })
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn with_mode(pattern: &str, mode: Mode) -> Result<Self> {
+        RegexBuilder::new(pattern).mode(mode).build()
     }
 
+    /// Returns true if the regex matches the whole text.
     pub fn matches(&self, text: &str) -> bool {
-        let mut virtual_machine = VirtualMachine::new(self.instructions.clone());
-        match virtual_machine.run(text.chars().collect()) {
-            Some(sp) => sp == text.len(),
+        let slots = match self.mode {
+            Mode::PikeVM => PikeVM::new(&self.instructions, self.slots).run(text, 0, true, true),
+            Mode::Backtrack => {
+                VirtualMachine::new(&self.instructions, 0, self.slots).run(text, true)
+            }
+        };
+
+        match slots {
+            Some(slots) => slots[1] == Some(text.len()),
             None => false,
         }
     }
+
+    /// Returns true if the regex matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the leftmost-first match in the text.
+    pub fn find(&self, text: &str) -> Option<Match> {
+        self.find_iter(text).next()
+    }
+
+    /// Returns an iterator over all non-overlapping leftmost-first matches in the text.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> FindIter<'r, 't> {
+        FindIter {
+            captures: self.captures_iter(text),
+        }
+    }
+
+    /// Returns the spans of the capture groups of the leftmost-first match in the text.
+    pub fn captures(&self, text: &str) -> Option<Captures> {
+        self.captures_at(text, 0)
+    }
+
+    /// Returns an iterator over the capture groups of all non-overlapping leftmost-first
+    /// matches in the text.
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> CapturesIter<'r, 't> {
+        CapturesIter {
+            regex: self,
+            text,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    /// Returns the program of the regex, one numbered instruction per line.
+    pub fn disassemble(&self) -> String {
+        let width = self.instructions.len().saturating_sub(1).to_string().len();
+
+        self.instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| format!("{pc:>width$}: {instruction}\n"))
+            .collect()
+    }
+
+    fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slots = self.slots_at(text, start)?;
+        let groups = slots
+            .chunks(2)
+            .map(|slot| match slot {
+                [Some(start), Some(end)] => Some(Match::new(*start, *end)),
+                _ => None,
+            })
+            .collect();
+
+        Some(Captures::new(groups).with_names(self.names.clone()))
+    }
+
+    // The slots hold byte offsets into `text`, as `start` is.
+    fn slots_at(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        match self.mode {
+            Mode::PikeVM => {
+                PikeVM::new(&self.instructions, self.slots).run(text, start, false, false)
+            }
+            Mode::Backtrack => text[start..]
+                .char_indices()
+                .map(|(offset, _)| start + offset)
+                .chain([text.len()])
+                .find_map(|sp| {
+                    VirtualMachine::new(&self.instructions, sp, self.slots).run(text, false)
+                }),
+        }
+    }
+}
+
+impl From<Node> for Regex {
+    /// Compiles the node without checking its length, to run in the default mode.
+    fn from(node: Node) -> Self {
+        Self {
+            instructions: node.compile(),
+            slots: 2 * (node.groups() + 1),
+            names: node.group_names().into(),
+            mode: Mode::default(),
+        }
+    }
+}
+
+impl parser::Regex for Regex {
+    fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.matches(text)
+    }
+
+    fn find(&self, text: &str) -> Option<Match> {
+        self.find(text)
+    }
+
+    fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Match> + 'a> {
+        Box::new(self.find_iter(text))
+    }
+
+    fn captures(&self, text: &str) -> Option<Captures> {
+        self.captures(text)
+    }
+
+    fn captures_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Captures> + 'a> {
+        Box::new(self.captures_iter(text))
+    }
+}
+
+/// An iterator over all non-overlapping matches in a text.
+///
+/// An empty match immediately following another match is skipped, and the
+/// search always advances by at least one char after an empty match.
+#[derive(Debug, Clone)]
+pub struct FindIter<'r, 't> {
+    captures: CapturesIter<'r, 't>,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.captures.next().and_then(|captures| captures.get(0))
+    }
+}
+
+/// An iterator over the capture groups of all non-overlapping matches in a text, which
+/// steps over the text as `FindIter` does.
+#[derive(Debug, Clone)]
+pub struct CapturesIter<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    last_end: usize,
+    last_match: Option<usize>,
+}
+
+impl Iterator for CapturesIter<'_, '_> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.last_end > self.text.len() {
+                return None;
+            }
+
+            let captures = self.regex.captures_at(self.text, self.last_end)?;
+            let m = captures.get(0)?;
+
+            if m.is_empty() {
+                self.last_end = match self.text[m.end()..].chars().next() {
+                    Some(c) => m.end() + c.len_utf8(),
+                    None => m.end() + 1,
+                };
+
+                if Some(m.end()) == self.last_match {
+                    continue;
+                }
+            } else {
+                self.last_end = m.end();
+            }
+
+            self.last_match = Some(m.end());
+            return Some(captures);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Regex;
+    use crate::{
+        Captures, LimitError, Mode, ParseError, ParseErrorKind, Regex, RegexBuilder, RegexExt,
+    };
+    use std::borrow::Cow;
 
     #[test]
     fn test_matches() {
@@ -42,5 +346,478 @@ mod tests {
         assert!(!regex.clone().matches("aa"));
         assert!(!regex.clone().matches("babab"));
         assert!(!regex.clone().matches("abbabba"));
+
+        let regex = Regex::new("[a-c][^a-c]*[ac]").unwrap();
+
+        assert!(regex.matches("ac"));
+        assert!(regex.matches("bxyzc"));
+
+        assert!(!regex.matches("abc"));
+        assert!(!regex.matches("dxa"));
+    }
+
+    #[test]
+    fn test_find() {
+        let regex = Regex::new("ab*").unwrap();
+
+        assert_eq!(regex.find("xxabbby").map(|m| m.range()), Some(2..6));
+        assert_eq!(regex.find("xxa").map(|m| m.range()), Some(2..3));
+        assert_eq!(regex.find("xxx"), None);
+
+        let regex = Regex::new("a|ab").unwrap();
+
+        assert_eq!(regex.find("cab").map(|m| m.range()), Some(1..2));
+
+        let regex = Regex::new("ab|a").unwrap();
+
+        assert_eq!(regex.find("cab").map(|m| m.range()), Some(1..3));
+
+        let regex = Regex::new("b*").unwrap();
+
+        assert_eq!(regex.find("abb").map(|m| m.range()), Some(0..0));
+
+        let regex = Regex::new("[0-9][0-9]*").unwrap();
+
+        assert_eq!(regex.find("abc 0123 45").map(|m| m.range()), Some(4..8));
+
+        let regex = Regex::new("é(a|ü)").unwrap();
+
+        assert_eq!(regex.find("xéü").map(|m| m.range()), Some(1..5));
+    }
+
+    #[test]
+    fn test_is_match() {
+        let regex = Regex::new("(a|b)*a(a|b)(a|b)").unwrap();
+
+        assert!(regex.is_match("xxaaayy"));
+        assert!(regex.is_match("ccbabbcc"));
+
+        assert!(!regex.is_match("xxaayy"));
+        assert!(!regex.is_match(""));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let ranges = |pattern: &str, text: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges("a*", "baaab"), vec![0..0, 1..4, 5..5]);
+        assert_eq!(ranges("ab|b", "abbab"), vec![0..2, 2..3, 3..5]);
+        assert_eq!(ranges("", "aé"), vec![0..0, 1..1, 3..3]);
+        assert_eq!(ranges("a|", "bab"), vec![0..0, 1..2, 3..3]);
+        assert!(ranges("x", "abc").is_empty());
+    }
+
+    #[test]
+    fn test_captures() {
+        let ranges = |pattern: &str, text: &str| {
+            Regex::new(pattern).unwrap().captures(text).map(|captures| {
+                captures
+                    .iter()
+                    .map(|m| m.map(|m| m.range()))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            ranges("(a|b)*c", "xabc"),
+            Some(vec![Some(1..4), Some(2..3)]),
+        );
+        assert_eq!(
+            ranges("(a)|(b)", "b"),
+            Some(vec![Some(0..1), None, Some(0..1)]),
+        );
+        assert_eq!(
+            ranges("((a)b)*", "ababc"),
+            Some(vec![Some(0..4), Some(2..4), Some(2..3)]),
+        );
+        assert_eq!(
+            ranges("(a|ab)(c|bcd)", "abcd"),
+            Some(vec![Some(0..4), Some(0..1), Some(1..4)]),
+        );
+        assert_eq!(
+            ranges("(é)(ü*)", "xéü"),
+            Some(vec![Some(1..5), Some(1..3), Some(3..5)]),
+        );
+        assert_eq!(ranges("(a)", "b"), None);
+    }
+
+    #[test]
+    fn test_named_captures() {
+        let regex = Regex::new(r"(?P<year>\d+)-(?<month>\d+)|(?<month>[a-z]+)").unwrap();
+        let captures = regex.captures("2024-05").unwrap();
+
+        assert_eq!(captures.name("year").map(|m| m.range()), Some(0..4));
+        assert_eq!(captures.name("month").map(|m| m.range()), Some(5..7));
+        assert_eq!(captures.name("day"), None);
+
+        let captures = regex.captures("may").unwrap();
+
+        assert_eq!(captures.name("year"), None);
+        assert_eq!(captures.name("month").map(|m| m.range()), Some(0..3));
+        assert_eq!(
+            regex
+                .captures_iter("5-1 x")
+                .map(|captures| captures.get(3).map(|m| m.range()))
+                .collect::<Vec<_>>(),
+            vec![None, Some(4..5)],
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new(r"(?<last>\w+), (\w+)").unwrap();
+        let text = "Doe, Jane; Roe, Rick";
+
+        assert_eq!(regex.replace(text, "$2 ${last}"), "Jane Doe; Roe, Rick");
+        assert_eq!(regex.replace_all(text, "$2 $last"), "Jane Doe; Rick Roe");
+        assert_eq!(regex.replace_all(text, "$$2"), "$2; $2");
+        assert_eq!(
+            regex.replacen("a, b; c, d; e, f", 2, "$2$1"),
+            "ba; dc; e, f"
+        );
+        assert_eq!(regex.replacen("a, b; c, d", 0, "$2$1"), "ba; dc");
+        assert_eq!(
+            regex.replace_all(text, |captures: &Captures| {
+                text[captures.get(2).unwrap().range()].to_uppercase()
+            }),
+            "JANE; RICK",
+        );
+        assert!(matches!(
+            regex.replace_all("none", "$1"),
+            Cow::Borrowed("none")
+        ));
+
+        let regex = Regex::new("a*").unwrap();
+
+        assert_eq!(regex.replace_all("baaab", "-"), "-b-b-");
+        assert_eq!(regex.replace_all("é", "-"), "-é-");
+    }
+
+    #[test]
+    fn test_modes() {
+        for mode in [Mode::PikeVM, Mode::Backtrack] {
+            let regex = Regex::with_mode("a|ab", mode).unwrap();
+
+            assert!(regex.matches("ab"));
+            assert_eq!(regex.find("ab").map(|m| m.range()), Some(0..1));
+
+            let regex = Regex::with_mode("(a|a)*b", mode).unwrap();
+            let text = "a".repeat(64);
+
+            assert!(!regex.matches(&text));
+            assert!(!regex.is_match(&text));
+
+            let regex = Regex::with_mode("(a*)*", mode).unwrap();
+
+            assert!(regex.matches("aaa"));
+            assert_eq!(regex.find("baa").map(|m| m.range()), Some(0..0));
+            assert_eq!(
+                regex.captures("aab").unwrap().get(1).map(|m| m.range()),
+                Some(0..2),
+            );
+
+            // A loop can be left after an iteration that matched nothing.
+            for (pattern, text, expected) in [
+                ("(|a)*", "aa", vec![0..0, 1..1, 2..2]),
+                ("(?:a??)+", "aa", vec![0..0, 1..1, 2..2]),
+                ("(a*?)+", "aa", vec![0..0, 1..1, 2..2]),
+                ("(a|)*", "aab", vec![0..2, 3..3]),
+                ("(?:b|a??)+", "abab", vec![0..0, 1..4]),
+            ] {
+                let regex = Regex::with_mode(pattern, mode).unwrap();
+
+                assert_eq!(
+                    regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(),
+                    expected,
+                    "{pattern}",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(
+            Regex::new(r"(a)+|.\b").unwrap().disassemble(),
+            concat!(
+                "0: split 1, 6\n",
+                "1: save 2\n",
+                "2: char 'a'\n",
+                "3: save 3\n",
+                "4: split 1, 5\n",
+                "5: jmp 8\n",
+                "6: class [\\0-\\t\\u{b}-\\u{10ffff}]\n",
+                "7: assert \\b\n",
+                "8: match\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        let ranges = |pattern: &str, text: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges("a+", "baaab"), vec![1..4]);
+        assert_eq!(ranges("a+?", "baaab"), vec![1..2, 2..3, 3..4]);
+        assert_eq!(ranges("ab?", "abaab"), vec![0..2, 2..3, 3..5]);
+        assert_eq!(ranges("ab??", "abaab"), vec![0..1, 2..3, 3..4]);
+        assert_eq!(ranges("a{2}", "aaaaa"), vec![0..2, 2..4]);
+        assert_eq!(ranges("a{2,}", "aaaaab"), vec![0..5]);
+        assert_eq!(ranges("a{2,3}", "aaaaa"), vec![0..3, 3..5]);
+        assert_eq!(ranges("a{2,3}?", "aaaaa"), vec![0..2, 2..4]);
+        assert_eq!(ranges("<[^>]*?>x", "<a><b>x"), vec![3..7]);
+
+        let regex = Regex::new("(a|b){2,3}?c").unwrap();
+
+        assert_eq!(
+            regex.captures("abbc").unwrap().get(1).map(|m| m.range()),
+            Some(2..3),
+        );
+
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(RegexBuilder::new("a{1001}")
+            .repeat_limit(2000)
+            .build()
+            .unwrap()
+            .matches(&"a".repeat(1001)));
+    }
+
+    #[test]
+    fn test_any() {
+        let regex = Regex::new("a.c").unwrap();
+
+        assert!(regex.matches("abc"));
+        assert!(regex.matches("a.c"));
+        assert!(regex.is_match("aéc"));
+        assert!(regex.is_match("a\u{10FFFF}c"));
+
+        assert!(!regex.matches("a\nc"));
+        assert!(!regex.matches("ac"));
+
+        assert_eq!(
+            regex
+                .find_iter("a\nc abc")
+                .map(|m| m.range())
+                .collect::<Vec<_>>(),
+            vec![4..7],
+        );
+
+        let regex = RegexBuilder::new("a.c")
+            .dot_matches_newline(true)
+            .build()
+            .unwrap();
+
+        assert!(regex.matches("a\nc"));
+
+        let regex = Regex::new("a.*c").unwrap();
+
+        assert_eq!(regex.find("xabcbc\nc").map(|m| m.range()), Some(1..6));
+    }
+
+    #[test]
+    fn test_assertion() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let multi_line = |pattern| RegexBuilder::new(pattern).multi_line(true).build().unwrap();
+
+        for mode in [Mode::PikeVM, Mode::Backtrack] {
+            let regex = |pattern| Regex::with_mode(pattern, mode).unwrap();
+
+            assert!(regex("^abc$").matches("abc"));
+            assert!(regex("^$").matches(""));
+            assert!(!regex("a^b").is_match("ab"));
+
+            assert_eq!(ranges(&regex("^a"), "aa\na"), vec![0..1]);
+            assert_eq!(ranges(&regex("a$"), "a\naa"), vec![3..4]);
+            assert_eq!(ranges(&regex(r"\Aa|b\z"), "abab"), vec![0..1, 3..4]);
+            assert_eq!(
+                ranges(&regex(r"\bfoo\b"), "foo foobar barfoo foo"),
+                vec![0..3, 18..21],
+            );
+            assert_eq!(ranges(&regex(r"\Bo\B"), "foo"), vec![1..2]);
+            assert_eq!(ranges(&regex(r"\bb"), "ab b"), vec![3..4]);
+            assert_eq!(ranges(&regex(r"\b"), "ab cd"), vec![0..0, 2..2, 3..3, 5..5]);
+        }
+
+        assert_eq!(ranges(&multi_line("^a"), "aa\na"), vec![0..1, 3..4]);
+        assert_eq!(ranges(&multi_line("a$"), "a\naa"), vec![0..1, 3..4]);
+        assert_eq!(ranges(&multi_line("^$"), "a\n\nb"), vec![2..2]);
+        assert_eq!(ranges(&multi_line(r"\Aa"), "a\na"), vec![0..1]);
+    }
+
+    #[test]
+    fn test_perl_class() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let unicode = |pattern| Regex::new(pattern).unwrap();
+        let ascii = |pattern| RegexBuilder::new(pattern).unicode(false).build().unwrap();
+
+        assert_eq!(ranges(&unicode(r"\d+"), "a12 \u{663}4"), vec![1..3, 4..7]);
+        assert_eq!(ranges(&ascii(r"\d+"), "a12 \u{663}4"), vec![1..3, 6..7]);
+        assert_eq!(ranges(&unicode(r"\w+"), "héllo wörld"), vec![0..6, 7..13]);
+        assert_eq!(
+            ranges(&ascii(r"\w+"), "héllo wörld"),
+            vec![0..1, 3..6, 7..8, 10..13],
+        );
+        assert_eq!(ranges(&unicode(r"\W"), "a-b"), vec![1..2]);
+        assert_eq!(ranges(&unicode(r"[\d\s]+"), "1 2x"), vec![0..3]);
+        assert_eq!(ranges(&unicode("[^\\S\n]+"), "a \t\nb"), vec![1..3]);
+        assert!(unicode(r"a\sb").is_match("a\u{3000}b"));
+        assert!(!ascii(r"a\sb").is_match("a\u{3000}b"));
+        assert!(!unicode(r"a\Db").is_match("a1b"));
+        assert_eq!(ranges(&unicode(r"\b"), "é"), vec![0..0, 2..2]);
+        assert!(ranges(&ascii(r"\b"), "é").is_empty());
+        assert_eq!(ranges(&ascii(r"\B.\b"), "éa"), vec![0..2]);
+    }
+
+    #[test]
+    fn test_property_class() {
+        let ranges = |pattern, text| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_iter(text)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges(r"\p{Greek}+", "abc αβγ def"), vec![4..10]);
+        assert_eq!(ranges(r"\p{Han}", "漢字abc"), vec![0..3, 3..6]);
+        assert_eq!(ranges(r"\P{L}+", "ab12cd"), vec![2..4]);
+        assert_eq!(ranges(r"[\p{Lu}\d]+", "aB1c"), vec![1..3]);
+        assert_eq!(ranges(r"\p{Nd}", "x\u{663}"), vec![1..3]);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let ranges =
+            |regex: &Regex, text| regex.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        let regex = |pattern| Regex::new(pattern).unwrap();
+        let case_insensitive = |pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            ranges(&case_insensitive("k"), "kK\u{212A}x"),
+            vec![0..1, 1..2, 2..5]
+        );
+        assert_eq!(ranges(&case_insensitive("[a-c]+"), "xAbCd"), vec![1..4]);
+        assert_eq!(ranges(&case_insensitive("[^k]"), "K\u{212A}kx"), vec![5..6]);
+        assert_eq!(ranges(&regex("(?i)σ"), "Σσς"), vec![0..2, 2..4, 4..6]);
+        assert_eq!(ranges(&regex("a(?i)b|c"), "aB AB C"), vec![0..2, 6..7]);
+        assert_eq!(ranges(&regex("(?i:a)b"), "Ab AB"), vec![0..2]);
+        assert_eq!(ranges(&case_insensitive("a(?-i)b"), "AB Ab"), vec![3..5]);
+        assert!(regex(r"(?i)\p{Lu}").is_match("a"));
+    }
+
+    #[test]
+    fn test_limit() {
+        let limit_error = |builder: &RegexBuilder| {
+            let error = builder.build().unwrap_err();
+            *error.downcast_ref::<LimitError>().unwrap()
+        };
+
+        assert_eq!(
+            limit_error(&RegexBuilder::new("(a{1000}){1000}")),
+            LimitError::ProgramLength { limit: 100_000 },
+        );
+        assert_eq!(
+            limit_error(RegexBuilder::new("a{50}").program_length_limit(50)),
+            LimitError::ProgramLength { limit: 50 },
+        );
+        assert!(RegexBuilder::new("a{50}")
+            .program_length_limit(51)
+            .build()
+            .is_ok());
+
+        let error =
+            Regex::new(&format!("{}a{}", "(".repeat(10_000), ")".repeat(10_000))).unwrap_err();
+        let error = error.downcast_ref::<ParseError>().unwrap();
+
+        assert_eq!(error.kind(), ParseErrorKind::NestingTooDeep { limit: 100 });
+        assert!(RegexBuilder::new("((a))").nest_limit(1).build().is_err());
+
+        for mode in [Mode::PikeVM, Mode::Backtrack] {
+            let regex = |pattern: &str| Regex::with_mode(pattern, mode).unwrap();
+
+            assert!(regex("((((a*)*)*)*)*").matches("aaa"));
+            assert!(!regex(&"a".repeat(20_000)).is_match("aaa"));
+            assert!(regex(&"a|".repeat(20_000)).is_match("b"));
+        }
+    }
+
+    #[test]
+    fn test_offsets() {
+        for mode in [Mode::PikeVM, Mode::Backtrack] {
+            let regex = |pattern| Regex::with_mode(pattern, mode).unwrap();
+
+            assert!(regex("é").matches("é"));
+            assert!(regex("a.c").matches("a\u{10FFFF}c"));
+            assert!(regex("[α-ω]+").matches("αβγ"));
+            assert!(!regex("..").matches("é"));
+            assert_eq!(regex("ü").find("aéü").map(|m| m.range()), Some(3..5));
+
+            let captures = regex("(é+)(.)").captures("xéé漢").unwrap();
+            let text = "xéé漢";
+
+            assert_eq!(captures.get(0).map(|m| &text[m.range()]), Some("éé漢"));
+            assert_eq!(captures.get(1).map(|m| &text[m.range()]), Some("éé"));
+            assert_eq!(captures.get(2).map(|m| &text[m.range()]), Some("漢"));
+        }
+    }
+
+    // Compares both modes with the regex crate on fixed and random patterns and on random
+    // texts over an alphabet mixing chars of every UTF-8 length.
+    #[test]
+    fn test_differential() {
+        let (patterns, texts) = parser::testing::cases();
+
+        for pattern in &patterns {
+            let expected = regex::Regex::new(pattern).unwrap();
+            let whole = regex::Regex::new(&format!("^(?:{pattern})$")).unwrap();
+
+            for mode in [Mode::PikeVM, Mode::Backtrack] {
+                let regex = Regex::with_mode(pattern, mode).unwrap();
+
+                for text in &texts {
+                    let groups = |captures: &Captures| {
+                        (0..captures.len())
+                            .map(|index| captures.get(index).map(|m| m.range()))
+                            .collect::<Vec<_>>()
+                    };
+
+                    assert_eq!(
+                        regex
+                            .captures_iter(text)
+                            .map(|c| groups(&c))
+                            .collect::<Vec<_>>(),
+                        expected
+                            .captures_iter(text)
+                            .map(|c| c.iter().map(|m| m.map(|m| m.range())).collect::<Vec<_>>())
+                            .collect::<Vec<_>>(),
+                        "{pattern:?} {text:?} {mode:?}",
+                    );
+                    assert_eq!(
+                        regex.matches(text),
+                        whole.is_match(text),
+                        "{pattern:?} {text:?} {mode:?}",
+                    );
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,244 @@
+use crate::compiler::Instruction;
+
+#[derive(Debug, Clone, PartialEq)]
+struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    fn new(capacity: usize) -> Self {
+        Self {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    fn contains(&self, value: usize) -> bool {
+        let index = self.sparse[value];
+        index < self.dense.len() && self.dense[index] == value
+    }
+
+    fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+
+        self.sparse[value] = self.dense.len();
+        self.dense.push(value);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Threads {
+    pcs: SparseSet,
+    slots: Vec<Option<usize>>,
+    stride: usize,
+}
+
+impl Threads {
+    fn new(instructions: usize, stride: usize) -> Self {
+        Self {
+            pcs: SparseSet::new(instructions),
+            slots: vec![None; instructions * stride],
+            stride,
+        }
+    }
+
+    fn slots(&self, pc: usize) -> &[Option<usize>] {
+        &self.slots[pc * self.stride..(pc + 1) * self.stride]
+    }
+
+    fn slots_mut(&mut self, pc: usize) -> &mut [Option<usize>] {
+        &mut self.slots[pc * self.stride..(pc + 1) * self.stride]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+/// Runs every thread in lockstep over the input, so that each pc is visited
+/// at most once per position and the search takes O(n·m) time.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PikeVM<'a> {
+    instructions: &'a [Instruction],
+    slots: usize,
+}
+
+impl<'a> PikeVM<'a> {
+    pub(crate) fn new(instructions: &'a [Instruction], slots: usize) -> Self {
+        Self {
+            instructions,
+            slots: slots.max(2),
+        }
+    }
+
+    /// Searches `text` from the byte offset `start`, returning the slots of the match,
+    /// which are byte offsets into `text`.
+    pub(crate) fn run(
+        &self,
+        text: &str,
+        start: usize,
+        anchored: bool,
+        anchored_end: bool,
+    ) -> Option<Vec<Option<usize>>> {
+        let mut current = Threads::new(self.instructions.len(), self.slots);
+        let mut next = Threads::new(self.instructions.len(), self.slots);
+        let mut stack = Vec::new();
+        let mut scratch = vec![None; self.slots];
+        let mut matched = None;
+        let mut sp = start;
+
+        loop {
+            if matched.is_none() && (sp == start || !anchored) {
+                scratch.fill(None);
+                scratch[0] = Some(sp);
+                self.add_thread(&mut current, &mut stack, text, 0, sp, &mut scratch);
+            }
+
+            if current.pcs.dense.is_empty() && (matched.is_some() || anchored) {
+                break;
+            }
+
+            let c = text[sp..].chars().next();
+            let next_sp = sp + c.map_or(0, char::len_utf8);
+
+            for i in 0..current.pcs.dense.len() {
+                let pc = current.pcs.dense[i];
+
+                match &self.instructions[pc] {
+                    Instruction::C(expected) if c == Some(*expected) => {
+                        scratch.copy_from_slice(current.slots(pc));
+                        self.add_thread(&mut next, &mut stack, text, pc + 1, next_sp, &mut scratch);
+                    }
+                    Instruction::R(class) if c.is_some_and(|c| class.contains(c)) => {
+                        scratch.copy_from_slice(current.slots(pc));
+                        self.add_thread(&mut next, &mut stack, text, pc + 1, next_sp, &mut scratch);
+                    }
+                    Instruction::M => {
+                        if anchored_end && sp != text.len() {
+                            continue;
+                        }
+
+                        let mut slots = current.slots(pc).to_vec();
+                        slots[1] = Some(sp);
+                        matched = Some(slots);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.pcs.clear();
+
+            if c.is_none() {
+                break;
+            }
+
+            sp = next_sp;
+        }
+
+        matched
+    }
+
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        stack: &mut Vec<Frame>,
+        text: &str,
+        pc: usize,
+        sp: usize,
+        slots: &mut [Option<usize>],
+    ) {
+        stack.push(Frame::Explore(pc));
+
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Explore(pc) => {
+                    if !threads.pcs.insert(pc) {
+                        continue;
+                    }
+
+                    match &self.instructions[pc] {
+                        Instruction::J(n) => {
+                            stack.push(Frame::Explore(*n));
+                        }
+                        Instruction::S(n, m) => {
+                            stack.push(Frame::Explore(*m));
+                            stack.push(Frame::Explore(*n));
+                        }
+                        Instruction::Save(n) => {
+                            stack.push(Frame::Restore(*n, slots[*n]));
+                            slots[*n] = Some(sp);
+                            stack.push(Frame::Explore(pc + 1));
+                        }
+                        Instruction::Assert(assertion) => {
+                            let behind = text[..sp].chars().next_back();
+
+                            if assertion.is_satisfied(behind, text[sp..].chars().next()) {
+                                stack.push(Frame::Explore(pc + 1));
+                            }
+                        }
+                        Instruction::C(_) | Instruction::R(_) | Instruction::M => {
+                            threads.slots_mut(pc).copy_from_slice(slots);
+                        }
+                    }
+                }
+                Frame::Restore(n, slot) => {
+                    slots[n] = slot;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::Compiler, pike_vm::PikeVM};
+    use parser::parse;
+
+    #[test]
+    fn test_run() {
+        let node = parse("(a|ab)(c|bcd)").unwrap();
+        let instructions = node.compile();
+
+        assert_eq!(
+            PikeVM::new(&instructions, 6).run("xabcd", 0, false, false),
+            Some(vec![Some(1), Some(5), Some(1), Some(2), Some(2), Some(5)]),
+        );
+        assert_eq!(
+            PikeVM::new(&instructions, 6).run("xabcd", 0, true, false),
+            None
+        );
+
+        let node = parse("a|ab").unwrap();
+        let instructions = node.compile();
+
+        assert_eq!(
+            PikeVM::new(&instructions, 2).run("ab", 0, true, false),
+            Some(vec![Some(0), Some(1)]),
+        );
+        assert_eq!(
+            PikeVM::new(&instructions, 2).run("ab", 0, true, true),
+            Some(vec![Some(0), Some(2)]),
+        );
+
+        let node = parse("(a*)*b").unwrap();
+        let instructions = node.compile();
+        let text = "a".repeat(1000);
+
+        assert_eq!(
+            PikeVM::new(&instructions, 4).run(&text, 0, false, false),
+            None
+        );
+    }
+}
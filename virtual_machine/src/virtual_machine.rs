@@ -1,52 +1,100 @@
 use crate::compiler::Instruction;
+use std::collections::HashSet;
 
 #[derive(Debug, Clone, PartialEq)]
 struct Thread {
     sp: usize,
     pc: usize,
+    slots: Vec<Option<usize>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct VirtualMachine {
-    instructions: Vec<Instruction>,
+pub(crate) struct VirtualMachine<'a> {
+    instructions: &'a [Instruction],
     threads: Vec<Thread>,
+    visited: HashSet<(usize, usize)>,
 }
 
-impl VirtualMachine {
-    pub(crate) fn new(instructions: Vec<Instruction>) -> Self {
+impl<'a> VirtualMachine<'a> {
+    pub(crate) fn new(instructions: &'a [Instruction], sp: usize, slots: usize) -> Self {
+        let mut slots = vec![None; slots.max(2)];
+        slots[0] = Some(sp);
+
         Self {
             instructions,
-            threads: vec![Thread { sp: 0, pc: 0 }],
+            threads: vec![Thread { sp, pc: 0, slots }],
+            visited: HashSet::new(),
         }
     }
 
-    pub(crate) fn run(&mut self, chars: Vec<char>) -> Option<usize> {
+    /// Runs the threads depth-first over `text`, in which the string pointers are byte
+    /// offsets.
+    pub(crate) fn run(&mut self, text: &str, anchored_end: bool) -> Option<Vec<Option<usize>>> {
         loop {
             match self.threads.last_mut() {
-                Some(current) => match self.instructions[current.pc] {
-                    Instruction::C(c) => {
-                        if current.sp < chars.len() && c == chars[current.sp] {
-                            current.sp += 1;
+                Some(current) => {
+                    // A thread that reaches a (pc, sp) pair that has already been explored
+                    // cannot succeed where the earlier one failed.
+                    if !self.visited.insert((current.pc, current.sp)) {
+                        self.threads.pop();
+                        continue;
+                    }
+
+                    match &self.instructions[current.pc] {
+                        Instruction::C(expected) => match text[current.sp..].chars().next() {
+                            Some(c) if c == *expected => {
+                                current.sp += c.len_utf8();
+                                current.pc += 1;
+                            }
+                            _ => {
+                                self.threads.pop();
+                            }
+                        },
+                        Instruction::R(class) => match text[current.sp..].chars().next() {
+                            Some(c) if class.contains(c) => {
+                                current.sp += c.len_utf8();
+                                current.pc += 1;
+                            }
+                            _ => {
+                                self.threads.pop();
+                            }
+                        },
+                        Instruction::J(n) => {
+                            current.pc = *n;
+                        }
+                        Instruction::S(n, m) => {
+                            let mut cloned = current.clone();
+
+                            current.pc = *n;
+                            cloned.pc = *m;
+
+                            self.threads.insert(self.threads.len() - 1, cloned);
+                        }
+                        Instruction::Save(n) => {
+                            current.slots[*n] = Some(current.sp);
                             current.pc += 1;
-                        } else {
-                            self.threads.pop();
                         }
-                    }
-                    Instruction::J(n) => {
-                        current.pc = n;
-                    }
-                    Instruction::S(n, m) => {
-                        let mut cloned = current.clone();
+                        Instruction::Assert(assertion) => {
+                            let behind = text[..current.sp].chars().next_back();
+                            let ahead = text[current.sp..].chars().next();
 
-                        current.pc = n;
-                        cloned.pc = m;
+                            if assertion.is_satisfied(behind, ahead) {
+                                current.pc += 1;
+                            } else {
+                                self.threads.pop();
+                            }
+                        }
+                        Instruction::M => {
+                            if anchored_end && current.sp != text.len() {
+                                self.threads.pop();
+                                continue;
+                            }
 
-                        self.threads.insert(self.threads.len() - 1, cloned);
-                    }
-                    Instruction::M => {
-                        return Some(current.sp);
+                            current.slots[1] = Some(current.sp);
+                            return self.threads.pop().map(|thread| thread.slots);
+                        }
                     }
-                },
+                }
                 None => {
                     return None;
                 }